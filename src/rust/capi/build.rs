@@ -0,0 +1,46 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// Generates beolyd5.h from the exported functions and types into OUT_DIR. The committed
+// include/beolyd5.h is only rewritten when BEOLYD5_UPDATE_HEADER is set.
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let config =
+        cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).expect("cbindgen.toml");
+
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=BEOLYD5_UPDATE_HEADER");
+
+    let bindings = match cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+    {
+        Ok(bindings) => bindings,
+        // Keep the committed header rather than failing the build
+        Err(err) => {
+            println!("cargo:warning=could not generate beolyd5.h: {}", err);
+            return;
+        }
+    };
+
+    let generated = out_dir.join("beolyd5.h");
+    bindings.write_to_file(&generated);
+
+    let committed = crate_dir.join("include").join("beolyd5.h");
+    if env::var_os("BEOLYD5_UPDATE_HEADER").is_some() {
+        fs::copy(&generated, &committed).expect("could not update include/beolyd5.h");
+    } else if fs::read(&generated).ok() != fs::read(&committed).ok() {
+        println!(
+            "cargo:warning=include/beolyd5.h is out of date, rebuild with BEOLYD5_UPDATE_HEADER=1"
+        );
+    }
+}
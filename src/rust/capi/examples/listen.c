@@ -0,0 +1,48 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+// Prints the events of the panel until Standby is pressed.
+//
+//   cargo build -p beolyd5_capi
+//   cc capi/examples/listen.c -Icapi/include -Ltarget/debug -lbeolyd5_c -o listen
+
+#include <stdio.h>
+#include "beolyd5.h"
+
+int main(void) {
+    Beolyd5Controller *controller = beolyd5_controller_new();
+    if (beolyd5_controller_open(controller) != BEOLYD5_OK) {
+        fprintf(stderr, "could not open the panel: %s\n", beolyd5_last_error());
+        beolyd5_controller_free(controller);
+        return 1;
+    }
+    beolyd5_controller_set_led(controller, BEOLYD5_LED_ON);
+
+    Beolyd5Event event;
+    int32_t status;
+    while ((status = beolyd5_controller_poll_event(controller, &event, 1000)) != BEOLYD5_CLOSED) {
+        if (status != BEOLYD5_OK) {
+            continue;
+        }
+        switch (event.kind) {
+        case BEOLYD5_EVENT_KIND_WHEEL_MOVED:
+            printf("wheel %d moved: %d\n", event.wheel, (int8_t)event.pos);
+            break;
+        case BEOLYD5_EVENT_KIND_BUTTON_PRESSED:
+            printf("button %d pressed\n", event.button);
+            beolyd5_controller_click(controller);
+            if (event.button == BEOLYD5_BUTTON_STANDBY) {
+                beolyd5_controller_close(controller);
+            }
+            break;
+        case BEOLYD5_EVENT_KIND_BUTTON_RELEASED:
+            printf("button %d released\n", event.button);
+            break;
+        }
+    }
+
+    beolyd5_controller_free(controller);
+    return 0;
+}
@@ -0,0 +1,168 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+#ifndef BEOLYD5_H
+#define BEOLYD5_H
+
+/* Generated by cbindgen from src/lib.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The call succeeded.
+#define BEOLYD5_OK 0
+
+// No event arrived before the timeout.
+#define BEOLYD5_TIMEOUT 1
+
+// The call failed, see `beolyd5_last_error`.
+#define BEOLYD5_ERROR -1
+
+// A pointer argument was `NULL`.
+#define BEOLYD5_INVALID_ARGUMENT -2
+
+// The controller is not open.
+#define BEOLYD5_CLOSED -3
+
+// A button of the panel.
+typedef enum Beolyd5Button {
+  BEOLYD5_BUTTON_NONE = 0,
+  BEOLYD5_BUTTON_LEFT = 1,
+  BEOLYD5_BUTTON_RIGHT = 2,
+  BEOLYD5_BUTTON_GO = 3,
+  BEOLYD5_BUTTON_STANDBY = 4,
+} Beolyd5Button;
+
+// What a `Beolyd5Event` is about.
+typedef enum Beolyd5EventKind {
+  // `wheel` moved: front and back wheels report relative movement in `pos`, the angular wheel its position.
+  BEOLYD5_EVENT_KIND_WHEEL_MOVED = 0,
+  // `button` went down.
+  BEOLYD5_EVENT_KIND_BUTTON_PRESSED = 1,
+  // `button` went up.
+  BEOLYD5_EVENT_KIND_BUTTON_RELEASED = 2,
+} Beolyd5EventKind;
+
+// The state of the LED.
+typedef enum Beolyd5Led {
+  BEOLYD5_LED_OFF = 0,
+  BEOLYD5_LED_ON = 1,
+  BEOLYD5_LED_BLINK = 2,
+} Beolyd5Led;
+
+// A wheel of the panel.
+typedef enum Beolyd5Wheel {
+  BEOLYD5_WHEEL_NONE = 0,
+  BEOLYD5_WHEEL_FRONT = 1,
+  BEOLYD5_WHEEL_ANGULAR = 2,
+  BEOLYD5_WHEEL_BACK = 3,
+} Beolyd5Wheel;
+
+// An opaque controller handle.
+typedef struct Beolyd5Controller Beolyd5Controller;
+
+// An event decoded from the panel. Fields that do not apply to `kind` are `NONE` or `0`.
+typedef struct Beolyd5Event {
+  enum Beolyd5EventKind kind;
+  enum Beolyd5Wheel wheel;
+  // Relative movement as a two's complement byte, or the angular position.
+  uint8_t pos;
+  enum Beolyd5Button button;
+} Beolyd5Event;
+
+// Called from the controller's read thread for every event. `event` is only valid during the call.
+typedef void (*Beolyd5EventCallback)(const struct Beolyd5Event *event, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Returns a description of the last error on the calling thread, or `NULL`.
+// The string is valid until the next failing call on the same thread.
+const char *beolyd5_last_error(void);
+
+// Creates a controller without opening it. Free it with `beolyd5_controller_free`.
+struct Beolyd5Controller *beolyd5_controller_new(void);
+
+// Closes and frees a controller created by `beolyd5_controller_new`. `NULL` is ignored.
+//
+// # Safety
+// `controller` must come from `beolyd5_controller_new` and must not be used afterwards. Must not
+// be called from an event callback.
+void beolyd5_controller_free(struct Beolyd5Controller *controller);
+
+// Opens the panel and starts reading events.
+//
+// # Safety
+// `controller` must come from `beolyd5_controller_new`.
+int32_t beolyd5_controller_open(struct Beolyd5Controller *controller);
+
+// Stops reading events.
+//
+// # Safety
+// `controller` must come from `beolyd5_controller_new`.
+int32_t beolyd5_controller_close(struct Beolyd5Controller *controller);
+
+// Waits up to `timeout_ms` milliseconds (`-1` blocks) for the next event and stores it in `event`.
+// Returns `BEOLYD5_TIMEOUT` when none arrived, and `BEOLYD5_CLOSED` once the controller is closed
+// and all events were polled. Events are not queued while an event callback is set.
+// Several threads may poll the same controller; each event is stored for only one of them.
+//
+// # Safety
+// `controller` must come from `beolyd5_controller_new` and `event` must point to writable memory.
+int32_t beolyd5_controller_poll_event(struct Beolyd5Controller *controller,
+                                      struct Beolyd5Event *event,
+                                      int32_t timeout_ms);
+
+// Calls `callback` with `user_data` from the read thread for every event, instead of queueing
+// events for `beolyd5_controller_poll_event`. Pass `NULL` to go back to polling.
+// May be called from the callback itself, e.g. to unsubscribe. A callback that is being called
+// while it is replaced finishes that call.
+//
+// # Safety
+// `controller` must come from `beolyd5_controller_new`, and `callback` must be safe to call with
+// `user_data` from another thread until it is replaced or the controller is freed.
+int32_t beolyd5_controller_set_event_callback(struct Beolyd5Controller *controller,
+                                              Beolyd5EventCallback callback,
+                                              void *user_data);
+
+// Sends a raw 2-byte output report.
+//
+// # Safety
+// `controller` must come from `beolyd5_controller_new`.
+int32_t beolyd5_controller_send(struct Beolyd5Controller *controller, uint8_t byte0, uint8_t byte1);
+
+// Switches the LED, keeping the backlight as it is.
+//
+// # Safety
+// `controller` must come from `beolyd5_controller_new`.
+int32_t beolyd5_controller_set_led(struct Beolyd5Controller *controller, enum Beolyd5Led led);
+
+// Turns the LCD backlight on or off, keeping the LED as it is.
+//
+// # Safety
+// `controller` must come from `beolyd5_controller_new`.
+int32_t beolyd5_controller_set_backlight(struct Beolyd5Controller *controller, bool on);
+
+// Makes the panel click.
+//
+// # Safety
+// `controller` must come from `beolyd5_controller_new`.
+int32_t beolyd5_controller_click(struct Beolyd5Controller *controller);
+
+// Plays the sound `code`.
+//
+// # Safety
+// `controller` must come from `beolyd5_controller_new`.
+int32_t beolyd5_controller_play_sound(struct Beolyd5Controller *controller, uint8_t code);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BEOLYD5_H */
@@ -0,0 +1,474 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! C API for `beolyd5_controller`, see `include/beolyd5.h`.
+//!
+//! Functions returning `int32_t` return one of the `BEOLYD5_*` status codes. After an error,
+//! `beolyd5_last_error` describes what went wrong on the calling thread.
+
+use beolyd5_controller::types::{Button as RustButton, Event, Led as RustLed, Wheel as RustWheel};
+use beolyd5_controller::Beolyd5Controller as Controller;
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{c_char, c_void, CString};
+use std::ptr;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// The call succeeded.
+pub const BEOLYD5_OK: i32 = 0;
+/// No event arrived before the timeout.
+pub const BEOLYD5_TIMEOUT: i32 = 1;
+/// The call failed, see `beolyd5_last_error`.
+pub const BEOLYD5_ERROR: i32 = -1;
+/// A pointer argument was `NULL`.
+pub const BEOLYD5_INVALID_ARGUMENT: i32 = -2;
+/// The controller is not open.
+pub const BEOLYD5_CLOSED: i32 = -3;
+
+/// A wheel of the panel.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Beolyd5Wheel {
+    None = 0,
+    Front = 1,
+    Angular = 2,
+    Back = 3,
+}
+
+/// A button of the panel.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Beolyd5Button {
+    None = 0,
+    Left = 1,
+    Right = 2,
+    Go = 3,
+    Standby = 4,
+}
+
+/// The state of the LED.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Beolyd5Led {
+    Off = 0,
+    On = 1,
+    Blink = 2,
+}
+
+/// What a `Beolyd5Event` is about.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Beolyd5EventKind {
+    /// `wheel` moved: front and back wheels report relative movement in `pos`, the angular wheel its position.
+    WheelMoved = 0,
+    /// `button` went down.
+    ButtonPressed = 1,
+    /// `button` went up.
+    ButtonReleased = 2,
+}
+
+/// An event decoded from the panel. Fields that do not apply to `kind` are `NONE` or `0`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Beolyd5Event {
+    pub kind: Beolyd5EventKind,
+    pub wheel: Beolyd5Wheel,
+    /// Relative movement as a two's complement byte, or the angular position.
+    pub pos: u8,
+    pub button: Beolyd5Button,
+}
+
+/// Called from the controller's read thread for every event. `event` is only valid during the call.
+pub type Beolyd5EventCallback =
+    Option<unsafe extern "C" fn(event: *const Beolyd5Event, user_data: *mut c_void)>;
+
+/// A callback and its user data, handed to the read thread.
+#[derive(Clone, Copy)]
+struct Subscription {
+    callback: unsafe extern "C" fn(*const Beolyd5Event, *mut c_void),
+    user_data: *mut c_void,
+}
+
+// SAFETY: the caller of `beolyd5_controller_set_event_callback` promises that `user_data` may be
+// used from the read thread.
+unsafe impl Send for Subscription {}
+
+/// An opaque controller handle.
+pub struct Beolyd5Controller {
+    inner: Controller,
+    // Locked while polling, so concurrent pollers take turns
+    events: Mutex<Receiver<Beolyd5Event>>,
+    subscription: Arc<Mutex<Option<Subscription>>>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl ToString) {
+    let message = CString::new(message.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn status(result: Result<(), Box<dyn Error>>) -> i32 {
+    match result {
+        Ok(()) => BEOLYD5_OK,
+        Err(err) => {
+            set_last_error(err);
+            BEOLYD5_ERROR
+        }
+    }
+}
+
+impl From<RustWheel> for Beolyd5Wheel {
+    fn from(wheel: RustWheel) -> Self {
+        match wheel {
+            RustWheel::None => Beolyd5Wheel::None,
+            RustWheel::Front => Beolyd5Wheel::Front,
+            RustWheel::Angular => Beolyd5Wheel::Angular,
+            RustWheel::Back => Beolyd5Wheel::Back,
+        }
+    }
+}
+
+impl From<RustButton> for Beolyd5Button {
+    fn from(button: RustButton) -> Self {
+        match button {
+            RustButton::None => Beolyd5Button::None,
+            RustButton::Left => Beolyd5Button::Left,
+            RustButton::Right => Beolyd5Button::Right,
+            RustButton::Go => Beolyd5Button::Go,
+            RustButton::Standby => Beolyd5Button::Standby,
+        }
+    }
+}
+
+impl From<Beolyd5Led> for RustLed {
+    fn from(led: Beolyd5Led) -> Self {
+        match led {
+            Beolyd5Led::Off => RustLed::Off,
+            Beolyd5Led::On => RustLed::On,
+            Beolyd5Led::Blink => RustLed::Blink,
+        }
+    }
+}
+
+impl From<Event> for Beolyd5Event {
+    fn from(event: Event) -> Self {
+        let (kind, wheel, pos, button) = match event {
+            Event::WheelMoved(wheel, pos) => (
+                Beolyd5EventKind::WheelMoved,
+                wheel.into(),
+                pos,
+                Beolyd5Button::None,
+            ),
+            Event::ButtonPressed(button) => (
+                Beolyd5EventKind::ButtonPressed,
+                Beolyd5Wheel::None,
+                0,
+                button.into(),
+            ),
+            Event::ButtonReleased(button) => (
+                Beolyd5EventKind::ButtonReleased,
+                Beolyd5Wheel::None,
+                0,
+                button.into(),
+            ),
+        };
+        Beolyd5Event {
+            kind,
+            wheel,
+            pos,
+            button,
+        }
+    }
+}
+
+/// Returns a description of the last error on the calling thread, or `NULL`.
+/// The string is valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn beolyd5_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Creates a controller without opening it. Free it with `beolyd5_controller_free`.
+#[no_mangle]
+pub extern "C" fn beolyd5_controller_new() -> *mut Beolyd5Controller {
+    let mut inner = Controller::new();
+    let (sender, events) = mpsc::channel();
+    let subscription: Arc<Mutex<Option<Subscription>>> = Arc::new(Mutex::new(None));
+
+    let callback_subscription = subscription.clone();
+    inner.register_event_callback(Arc::new(Mutex::new(
+        move |event: Event| -> Result<(), Box<dyn Error + Send>> {
+            let event = Beolyd5Event::from(event);
+            // Not locked during the call, so the callback may replace itself
+            let current = *callback_subscription.lock().unwrap();
+            match current {
+                // SAFETY: the callback and user data were handed to us together, and the event outlives the call.
+                Some(subscription) => unsafe {
+                    (subscription.callback)(&event, subscription.user_data)
+                },
+                None => {
+                    let _ = sender.send(event);
+                }
+            }
+            Ok(())
+        },
+    )));
+
+    Box::into_raw(Box::new(Beolyd5Controller {
+        inner,
+        events: Mutex::new(events),
+        subscription,
+    }))
+}
+
+/// Closes and frees a controller created by `beolyd5_controller_new`. `NULL` is ignored.
+///
+/// # Safety
+/// `controller` must come from `beolyd5_controller_new` and must not be used afterwards. Must not
+/// be called from an event callback.
+#[no_mangle]
+pub unsafe extern "C" fn beolyd5_controller_free(controller: *mut Beolyd5Controller) {
+    if !controller.is_null() {
+        // SAFETY: guaranteed by the caller.
+        let controller = unsafe { Box::from_raw(controller) };
+        controller.inner.close();
+    }
+}
+
+/// Opens the panel and starts reading events.
+///
+/// # Safety
+/// `controller` must come from `beolyd5_controller_new`.
+#[no_mangle]
+pub unsafe extern "C" fn beolyd5_controller_open(controller: *mut Beolyd5Controller) -> i32 {
+    // SAFETY: guaranteed by the caller.
+    let Some(controller) = (unsafe { controller.as_mut() }) else {
+        return BEOLYD5_INVALID_ARGUMENT;
+    };
+    status(controller.inner.open())
+}
+
+/// Stops reading events.
+///
+/// # Safety
+/// `controller` must come from `beolyd5_controller_new`.
+#[no_mangle]
+pub unsafe extern "C" fn beolyd5_controller_close(controller: *mut Beolyd5Controller) -> i32 {
+    // SAFETY: guaranteed by the caller.
+    let Some(controller) = (unsafe { controller.as_ref() }) else {
+        return BEOLYD5_INVALID_ARGUMENT;
+    };
+    controller.inner.close();
+    BEOLYD5_OK
+}
+
+/// Waits up to `timeout_ms` milliseconds (`-1` blocks) for the next event and stores it in `event`.
+/// Returns `BEOLYD5_TIMEOUT` when none arrived, and `BEOLYD5_CLOSED` once the controller is closed
+/// and all events were polled. Events are not queued while an event callback is set.
+/// Several threads may poll the same controller; each event is stored for only one of them.
+///
+/// # Safety
+/// `controller` must come from `beolyd5_controller_new` and `event` must point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn beolyd5_controller_poll_event(
+    controller: *mut Beolyd5Controller,
+    event: *mut Beolyd5Event,
+    timeout_ms: i32,
+) -> i32 {
+    // SAFETY: guaranteed by the caller.
+    let Some(controller) = (unsafe { controller.as_ref() }) else {
+        return BEOLYD5_INVALID_ARGUMENT;
+    };
+    if event.is_null() {
+        return BEOLYD5_INVALID_ARGUMENT;
+    }
+
+    let step = Duration::from_millis(100);
+    let mut remaining = (timeout_ms >= 0).then(|| Duration::from_millis(timeout_ms as u64));
+    loop {
+        let wait = remaining.map_or(step, |r| r.min(step));
+        let received = controller
+            .events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .recv_timeout(wait);
+        match received {
+            Ok(received) => {
+                // SAFETY: checked for NULL above, the caller guarantees it is writable.
+                unsafe { event.write(received) };
+                return BEOLYD5_OK;
+            }
+            Err(RecvTimeoutError::Disconnected) => return BEOLYD5_CLOSED,
+            Err(RecvTimeoutError::Timeout) if !controller.inner.is_open() => return BEOLYD5_CLOSED,
+            Err(RecvTimeoutError::Timeout) => match remaining {
+                Some(r) if r <= wait => return BEOLYD5_TIMEOUT,
+                Some(r) => remaining = Some(r - wait),
+                None => (),
+            },
+        }
+    }
+}
+
+/// Calls `callback` with `user_data` from the read thread for every event, instead of queueing
+/// events for `beolyd5_controller_poll_event`. Pass `NULL` to go back to polling.
+/// May be called from the callback itself, e.g. to unsubscribe. A callback that is being called
+/// while it is replaced finishes that call.
+///
+/// # Safety
+/// `controller` must come from `beolyd5_controller_new`, and `callback` must be safe to call with
+/// `user_data` from another thread until it is replaced or the controller is freed.
+#[no_mangle]
+pub unsafe extern "C" fn beolyd5_controller_set_event_callback(
+    controller: *mut Beolyd5Controller,
+    callback: Beolyd5EventCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    // SAFETY: guaranteed by the caller.
+    let Some(controller) = (unsafe { controller.as_ref() }) else {
+        return BEOLYD5_INVALID_ARGUMENT;
+    };
+    *controller.subscription.lock().unwrap() = callback.map(|callback| Subscription {
+        callback,
+        user_data,
+    });
+    BEOLYD5_OK
+}
+
+/// Sends a raw 2-byte output report.
+///
+/// # Safety
+/// `controller` must come from `beolyd5_controller_new`.
+#[no_mangle]
+pub unsafe extern "C" fn beolyd5_controller_send(
+    controller: *mut Beolyd5Controller,
+    byte0: u8,
+    byte1: u8,
+) -> i32 {
+    // SAFETY: guaranteed by the caller.
+    match unsafe { controller.as_ref() } {
+        Some(controller) => status(controller.inner.send([byte0, byte1])),
+        None => BEOLYD5_INVALID_ARGUMENT,
+    }
+}
+
+/// Switches the LED, keeping the backlight as it is.
+///
+/// # Safety
+/// `controller` must come from `beolyd5_controller_new`.
+#[no_mangle]
+pub unsafe extern "C" fn beolyd5_controller_set_led(
+    controller: *mut Beolyd5Controller,
+    led: Beolyd5Led,
+) -> i32 {
+    // SAFETY: guaranteed by the caller.
+    match unsafe { controller.as_ref() } {
+        Some(controller) => status(controller.inner.set_led(led.into())),
+        None => BEOLYD5_INVALID_ARGUMENT,
+    }
+}
+
+/// Turns the LCD backlight on or off, keeping the LED as it is.
+///
+/// # Safety
+/// `controller` must come from `beolyd5_controller_new`.
+#[no_mangle]
+pub unsafe extern "C" fn beolyd5_controller_set_backlight(
+    controller: *mut Beolyd5Controller,
+    on: bool,
+) -> i32 {
+    // SAFETY: guaranteed by the caller.
+    match unsafe { controller.as_ref() } {
+        Some(controller) => status(controller.inner.set_backlight(on)),
+        None => BEOLYD5_INVALID_ARGUMENT,
+    }
+}
+
+/// Makes the panel click.
+///
+/// # Safety
+/// `controller` must come from `beolyd5_controller_new`.
+#[no_mangle]
+pub unsafe extern "C" fn beolyd5_controller_click(controller: *mut Beolyd5Controller) -> i32 {
+    // SAFETY: guaranteed by the caller.
+    match unsafe { controller.as_ref() } {
+        Some(controller) => status(controller.inner.click()),
+        None => BEOLYD5_INVALID_ARGUMENT,
+    }
+}
+
+/// Plays the sound `code`.
+///
+/// # Safety
+/// `controller` must come from `beolyd5_controller_new`.
+#[no_mangle]
+pub unsafe extern "C" fn beolyd5_controller_play_sound(
+    controller: *mut Beolyd5Controller,
+    code: u8,
+) -> i32 {
+    // SAFETY: guaranteed by the caller.
+    match unsafe { controller.as_ref() } {
+        Some(controller) => status(controller.inner.play_sound(code)),
+        None => BEOLYD5_INVALID_ARGUMENT,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn converts_events() {
+        assert_eq!(
+            Beolyd5Event::from(Event::WheelMoved(RustWheel::Back, 0xff)),
+            Beolyd5Event {
+                kind: Beolyd5EventKind::WheelMoved,
+                wheel: Beolyd5Wheel::Back,
+                pos: 0xff,
+                button: Beolyd5Button::None,
+            }
+        );
+        assert_eq!(
+            Beolyd5Event::from(Event::ButtonReleased(RustButton::Go)).button,
+            Beolyd5Button::Go
+        );
+    }
+
+    #[test]
+    fn reports_errors_on_a_closed_controller() {
+        let controller = beolyd5_controller_new();
+        let mut event = Beolyd5Event::from(Event::ButtonPressed(RustButton::None));
+
+        unsafe {
+            assert_eq!(
+                beolyd5_controller_poll_event(controller, &mut event, 0),
+                BEOLYD5_CLOSED
+            );
+            assert_eq!(
+                beolyd5_controller_poll_event(controller, ptr::null_mut(), 0),
+                BEOLYD5_INVALID_ARGUMENT
+            );
+            assert_eq!(beolyd5_controller_send(controller, 0x40, 0), BEOLYD5_ERROR);
+            let message = CStr::from_ptr(beolyd5_last_error()).to_string_lossy();
+            assert!(message.contains("not found"), "{}", message);
+            beolyd5_controller_free(controller);
+        }
+    }
+
+    #[test]
+    fn handle_can_be_polled_from_several_threads() {
+        fn assert_sync<T: Sync>() {}
+        assert_sync::<Beolyd5Controller>();
+    }
+}
@@ -0,0 +1,436 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Pure decoding of BeoSound 5 input reports.
+//!
+//! The controller sends a 6-byte input report whenever something changes on the panel:
+//!
+//! | Byte | Meaning                                            |
+//! |------|----------------------------------------------------|
+//! | 0    | Front wheel movement (relative, `0` when untouched) |
+//! | 1    | Back wheel movement (relative, `0` when untouched)  |
+//! | 2    | Angular wheel (pointer) absolute position           |
+//! | 3    | Button bits                                         |
+//! | 4, 5 | Unknown                                             |
+//!
+//! [`decode_events`] turns a report into [`Event`]s without touching any shared state or
+//! allocating, so the same logic runs in `beolyd5_controller` and on firmware.
+
+use crate::types::{Button, Event, Wheel};
+use core::ops::Deref;
+
+/// Length of an input report in bytes.
+pub const REPORT_LEN: usize = 6;
+/// Length of an output report in bytes.
+pub const OUTPUT_REPORT_LEN: usize = 2;
+
+/// Offset of the front wheel byte in an input report.
+pub const FRONT_WHEEL_BYTE: usize = 0;
+/// Offset of the back wheel byte in an input report.
+pub const BACK_WHEEL_BYTE: usize = 1;
+/// Offset of the angular wheel byte in an input report.
+pub const ANGULAR_WHEEL_BYTE: usize = 2;
+/// Offset of the button byte in an input report.
+pub const BUTTON_BYTE: usize = 3;
+
+/// Highest angular wheel position the panel reports.
+pub const ANGULAR_MAX: u8 = 120;
+
+/// Most events a single report decodes to: all three wheels, a button released and another pressed.
+pub const MAX_EVENTS: usize = 5;
+
+/// `DecoderState` is everything [`decode_events`] needs to remember between two reports.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecoderState {
+    /// The last report that was decoded.
+    pub last_report: [u8; REPORT_LEN],
+    /// The button currently held down, or `Button::None`.
+    pub button_held: Button,
+}
+
+/// `Events` holds the events decoded from one report, in order. It derefs to a slice.
+#[derive(Debug, Copy, Clone)]
+pub struct Events {
+    events: [Event; MAX_EVENTS],
+    len: usize,
+}
+
+impl Events {
+    fn new() -> Events {
+        Events {
+            events: [Event::ButtonReleased(Button::None); MAX_EVENTS],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, event: Event) {
+        self.events[self.len] = event;
+        self.len += 1;
+    }
+}
+
+impl Deref for Events {
+    type Target = [Event];
+
+    fn deref(&self) -> &[Event] {
+        &self.events[..self.len]
+    }
+}
+
+impl PartialEq for Events {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<'a> IntoIterator for &'a Events {
+    type Item = &'a Event;
+    type IntoIter = core::slice::Iter<'a, Event>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Decodes a single input report against the previous decoder state.
+///
+/// Returns the events found in the report together with the state to pass to the next call.
+/// The function has no side effects; decoding the same report against the same state always
+/// yields the same result.
+///
+/// Every change in the report is decoded, in this order: the wheels that moved (Front, Angular,
+/// Back), then the release of the button held so far, then the press of a new button.
+pub fn decode_events(report: [u8; REPORT_LEN], state: &DecoderState) -> (Events, DecoderState) {
+    let mut events = Events::new();
+    for (wheel, pos) in wheels_moved(report, state.last_report) {
+        events.push(Event::WheelMoved(wheel, pos));
+    }
+
+    let button = button_pressed(report);
+    if button != state.button_held {
+        if state.button_held != Button::None {
+            events.push(Event::ButtonReleased(state.button_held));
+        }
+        if button != Button::None {
+            events.push(Event::ButtonPressed(button));
+        }
+    }
+
+    let next = DecoderState {
+        last_report: report,
+        button_held: button,
+    };
+    (events, next)
+}
+
+/// Like [`decode_events`], returning the events as a `Vec`.
+#[cfg(feature = "alloc")]
+pub fn decode(
+    report: [u8; REPORT_LEN],
+    state: &DecoderState,
+) -> (alloc::vec::Vec<Event>, DecoderState) {
+    let (events, next) = decode_events(report, state);
+    (events.to_vec(), next)
+}
+
+/// Returns the wheels that moved in `report` with their positions, in the order Front, Angular, Back.
+///
+/// Front and back wheels are only untouched if they are 0.
+/// The angular wheel is only untouched if it is the same as the last reading.
+pub fn wheels_moved(
+    report: [u8; REPORT_LEN],
+    last_report: [u8; REPORT_LEN],
+) -> impl Iterator<Item = (Wheel, u8)> {
+    let front_wheel_pos = report[FRONT_WHEEL_BYTE];
+    let angular_wheel_pos = report[ANGULAR_WHEEL_BYTE];
+    let back_wheel_pos = report[BACK_WHEEL_BYTE];
+
+    [
+        (front_wheel_pos != 0).then_some((Wheel::Front, front_wheel_pos)),
+        (last_report[ANGULAR_WHEEL_BYTE] != angular_wheel_pos)
+            .then_some((Wheel::Angular, angular_wheel_pos)),
+        (back_wheel_pos != 0).then_some((Wheel::Back, back_wheel_pos)),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// Returns the first wheel that moved in `report`, in the order Front, Angular, Back, or
+/// `(Wheel::None, 0)`. See [`wheels_moved`] for all of them.
+pub fn wheel_moved(report: [u8; REPORT_LEN], last_report: [u8; REPORT_LEN]) -> (Wheel, u8) {
+    wheels_moved(report, last_report)
+        .next()
+        .unwrap_or((Wheel::None, 0))
+}
+
+/// Returns the button held down in `report`, or `Button::None`.
+pub fn button_pressed(report: [u8; REPORT_LEN]) -> Button {
+    match report[BUTTON_BYTE] {
+        0x00 => Button::None,
+        0x20 => Button::Left,
+        0x10 => Button::Right,
+        0x40 => Button::Go,
+        0x80 => Button::Standby,
+        _ => Button::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn report(front: u8, back: u8, angular: u8, buttons: u8) -> [u8; REPORT_LEN] {
+        [front, back, angular, buttons, 0, 0]
+    }
+
+    fn decode_all(reports: &[[u8; REPORT_LEN]]) -> (Vec<Event>, DecoderState) {
+        let mut state = DecoderState::default();
+        let mut events = Vec::new();
+        for r in reports {
+            let (decoded, next) = decode_events(*r, &state);
+            events.extend_from_slice(&decoded);
+            state = next;
+        }
+        (events, state)
+    }
+
+    #[test]
+    fn idle_report_yields_nothing() {
+        let (events, state) = decode_events(report(0, 0, 0, 0), &DecoderState::default());
+        assert!(events.is_empty());
+        assert_eq!(state, DecoderState::default());
+    }
+
+    #[test]
+    fn front_wheel_movement() {
+        let (events, _) = decode_events(report(0x01, 0, 0, 0), &DecoderState::default());
+        assert_eq!(*events, [Event::WheelMoved(Wheel::Front, 0x01)]);
+    }
+
+    #[test]
+    fn back_wheel_movement() {
+        let (events, _) = decode_events(report(0, 0xff, 0, 0), &DecoderState::default());
+        assert_eq!(*events, [Event::WheelMoved(Wheel::Back, 0xff)]);
+    }
+
+    #[test]
+    fn angular_wheel_only_reported_on_change() {
+        let (events, state) = decode_events(report(0, 0, 0x40, 0), &DecoderState::default());
+        assert_eq!(*events, [Event::WheelMoved(Wheel::Angular, 0x40)]);
+
+        let (events, _) = decode_events(report(0, 0, 0x40, 0), &state);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn button_press_and_release() {
+        let (events, _) = decode_all(&[report(0, 0, 0, 0x40), report(0, 0, 0, 0)]);
+        assert_eq!(
+            events,
+            vec![
+                Event::ButtonPressed(Button::Go),
+                Event::ButtonReleased(Button::Go)
+            ]
+        );
+    }
+
+    #[test]
+    fn button_change_releases_previous_button() {
+        let (events, state) = decode_all(&[report(0, 0, 0, 0x20), report(0, 0, 0, 0x10)]);
+        assert_eq!(
+            events,
+            vec![
+                Event::ButtonPressed(Button::Left),
+                Event::ButtonReleased(Button::Left),
+                Event::ButtonPressed(Button::Right),
+            ]
+        );
+        assert_eq!(state.button_held, Button::Right);
+    }
+
+    #[test]
+    fn button_is_decoded_while_a_wheel_moves() {
+        let (events, state) = decode_events(report(0x01, 0, 0, 0x40), &DecoderState::default());
+        assert_eq!(
+            *events,
+            [
+                Event::WheelMoved(Wheel::Front, 0x01),
+                Event::ButtonPressed(Button::Go)
+            ]
+        );
+        assert_eq!(state.button_held, Button::Go);
+
+        let (events, _) = decode_events(report(0, 0, 0, 0x40), &state);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn every_change_in_a_report_is_decoded_in_order() {
+        let state = DecoderState {
+            last_report: report(0, 0, 0x30, 0x20),
+            button_held: Button::Left,
+        };
+        let (events, state) = decode_events(report(0xfe, 0x03, 0x31, 0x10), &state);
+
+        assert_eq!(
+            *events,
+            [
+                Event::WheelMoved(Wheel::Front, 0xfe),
+                Event::WheelMoved(Wheel::Angular, 0x31),
+                Event::WheelMoved(Wheel::Back, 0x03),
+                Event::ButtonReleased(Button::Left),
+                Event::ButtonPressed(Button::Right),
+            ]
+        );
+        assert_eq!(state.button_held, Button::Right);
+    }
+
+    /// Hand-built report sequences for input that used to be lost, not captured from a panel.
+    #[test]
+    fn regression_reports() {
+        // Go pressed and released while the front wheel turns, the pointer resting at 0x3c
+        let (events, _) = decode_all(&[
+            [0x00, 0x00, 0x3c, 0x00, 0x00, 0x00],
+            [0x01, 0x00, 0x3c, 0x40, 0x00, 0x00],
+            [0x01, 0x00, 0x3c, 0x40, 0x00, 0x00],
+            [0x02, 0x00, 0x3c, 0x00, 0x00, 0x00],
+        ]);
+        assert_eq!(
+            events,
+            vec![
+                Event::WheelMoved(Wheel::Angular, 0x3c),
+                Event::WheelMoved(Wheel::Front, 0x01),
+                Event::ButtonPressed(Button::Go),
+                Event::WheelMoved(Wheel::Front, 0x01),
+                Event::WheelMoved(Wheel::Front, 0x02),
+                Event::ButtonReleased(Button::Go),
+            ]
+        );
+
+        // The back wheel nudged while the pointer moves
+        let (events, _) = decode_all(&[
+            [0x00, 0x00, 0x3c, 0x00, 0x00, 0x00],
+            [0x00, 0xff, 0x3d, 0x00, 0x00, 0x00],
+            [0x00, 0xff, 0x3d, 0x00, 0x00, 0x00],
+        ]);
+        assert_eq!(
+            events,
+            vec![
+                Event::WheelMoved(Wheel::Angular, 0x3c),
+                Event::WheelMoved(Wheel::Angular, 0x3d),
+                Event::WheelMoved(Wheel::Back, 0xff),
+                Event::WheelMoved(Wheel::Back, 0xff),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_button_bits_are_ignored() {
+        let (events, _) = decode_events(report(0, 0, 0, 0x01), &DecoderState::default());
+        assert!(events.is_empty());
+    }
+
+    fn any_report() -> impl Strategy<Value = [u8; REPORT_LEN]> {
+        (
+            prop_oneof![Just(0u8), any::<u8>()],
+            prop_oneof![Just(0u8), any::<u8>()],
+            any::<u8>(),
+            prop::sample::select(vec![0x00u8, 0x10, 0x20, 0x40, 0x80, 0x01, 0x60]),
+            any::<u8>(),
+            any::<u8>(),
+        )
+            .prop_map(|(front, back, angular, buttons, b4, b5)| {
+                [front, back, angular, buttons, b4, b5]
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn decode_is_deterministic(r in any_report(), last in any_report()) {
+            let state = DecoderState { last_report: last, button_held: button_pressed(last) };
+            prop_assert_eq!(decode_events(r, &state), decode_events(r, &state));
+        }
+
+        #[test]
+        fn no_event_from_an_unchanged_report(r in any_report()) {
+            let idle = [0, 0, r[ANGULAR_WHEEL_BYTE], r[BUTTON_BYTE], r[4], r[5]];
+            let state = DecoderState { last_report: idle, button_held: button_pressed(idle) };
+            let (events, next) = decode_events(idle, &state);
+            prop_assert!(events.is_empty());
+            prop_assert_eq!(next, state);
+        }
+
+        #[test]
+        fn every_moved_wheel_is_reported_once(r in any_report(), last in any_report()) {
+            let state = DecoderState { last_report: last, button_held: Button::None };
+            let (events, _) = decode_events(r, &state);
+            let wheels: Vec<(Wheel, u8)> = events
+                .iter()
+                .filter_map(|e| match e {
+                    Event::WheelMoved(wheel, pos) => Some((*wheel, *pos)),
+                    _ => None,
+                })
+                .collect();
+            // Straight from the report layout: byte 0 front, byte 2 angular, byte 1 back
+            let mut expected = Vec::new();
+            if r[0] != 0 {
+                expected.push((Wheel::Front, r[0]));
+            }
+            if r[2] != last[2] {
+                expected.push((Wheel::Angular, r[2]));
+            }
+            if r[1] != 0 {
+                expected.push((Wheel::Back, r[1]));
+            }
+            prop_assert_eq!(wheels, expected);
+        }
+
+        #[test]
+        fn buttons_follow_the_wheels(r in any_report(), last in any_report()) {
+            let state = DecoderState { last_report: last, button_held: button_pressed(last) };
+            let (events, next) = decode_events(r, &state);
+            let first_button = events.iter().position(|e| !matches!(e, Event::WheelMoved(..)));
+            if let Some(first_button) = first_button {
+                prop_assert!(events[first_button..].iter().all(|e| !matches!(e, Event::WheelMoved(..))));
+            }
+            prop_assert_eq!(next.button_held, button_pressed(r));
+        }
+
+        #[test]
+        fn state_tracks_the_last_report(r in any_report(), last in any_report()) {
+            let state = DecoderState { last_report: last, button_held: Button::None };
+            let (_, next) = decode_events(r, &state);
+            prop_assert_eq!(next.last_report, r);
+        }
+
+        #[test]
+        fn every_press_has_a_matching_release(reports in prop::collection::vec(any_report(), 0..64)) {
+            let mut all = reports.clone();
+            let angular = reports.last().map(|r| r[ANGULAR_WHEEL_BYTE]).unwrap_or(0);
+            all.push(report(0, 0, angular, 0));
+            let (events, state) = decode_all(&all);
+
+            let mut held = Button::None;
+            for event in events {
+                match event {
+                    Event::ButtonPressed(b) => {
+                        prop_assert_eq!(held, Button::None);
+                        prop_assert_ne!(b, Button::None);
+                        held = b;
+                    }
+                    Event::ButtonReleased(b) => {
+                        prop_assert_eq!(held, b);
+                        held = Button::None;
+                    }
+                    Event::WheelMoved(..) => {}
+                }
+            }
+            prop_assert_eq!(held, Button::None);
+            prop_assert_eq!(state.button_held, Button::None);
+        }
+    }
+}
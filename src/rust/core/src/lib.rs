@@ -0,0 +1,22 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! The BeoSound 5 protocol without the USB part: report layouts, the panel types and the decoder.
+//!
+//! This crate is `no_std` and allocation free so the same logic can run on a microcontroller
+//! sitting between the panel and a host, e.g. to bridge it to BLE or serial.
+//! `beolyd5_controller` builds on it and re-exports it as `types` and `decoder`.
+//!
+//! Features:
+//! - `serde` derives `Serialize` and `Deserialize` for the types.
+//! - `alloc` adds `decoder::decode`, which returns the events as a `Vec`.
+
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod decoder;
+pub mod types;
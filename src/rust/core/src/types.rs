@@ -0,0 +1,129 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+use core::fmt;
+
+/// `Button` represents one of the four buttons on the BeoSound 5 controller.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Button {
+    #[default]
+    None,
+    Left,
+    Right,
+    Go,
+    Standby,
+}
+
+impl fmt::Display for Button {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Button::None => write!(f, "None"),
+            Button::Left => write!(f, "Left"),
+            Button::Right => write!(f, "Right"),
+            Button::Go => write!(f, "Go"),
+            Button::Standby => write!(f, "Standby"),
+        }
+    }
+}
+
+/// `Wheel` represents one of the three wheels on the BeoSound 5 controller.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Wheel {
+    Front,
+    Angular,
+    Back,
+    None,
+}
+
+impl fmt::Display for Wheel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Wheel::Front => write!(f, "Front"),
+            Wheel::Angular => write!(f, "Angular"),
+            Wheel::Back => write!(f, "Back"),
+            Wheel::None => write!(f, "None"),
+        }
+    }
+}
+
+/// `Event` is a single change decoded from an input report of the BeoSound 5 controller.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+    /// A wheel moved. Front and back wheels report relative movement, the angular wheel its position.
+    WheelMoved(Wheel, u8),
+    /// A button went down.
+    ButtonPressed(Button),
+    /// A button that was previously pressed went up.
+    ButtonReleased(Button),
+}
+
+/// `Led` represents the state of the LED on the BeoSound 5 controller.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Led {
+    #[default]
+    Off,
+    On,
+    Blink,
+}
+
+impl fmt::Display for Led {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Led::Off => write!(f, "Off"),
+            Led::On => write!(f, "On"),
+            Led::Blink => write!(f, "Blink"),
+        }
+    }
+}
+
+/// `PanelOutput` is the panel state described by a 2-byte output report.
+///
+/// Byte 0 holds the LED (`0x80` solid, `0x10` blink), the LCD backlight (`0x40`) and click (`0x0f`) bits.
+/// Byte 1 selects a sound, see `examples/listen.rs`.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PanelOutput {
+    pub led: Led,
+    pub backlight: bool,
+    pub click: bool,
+    pub sound: u8,
+}
+
+impl PanelOutput {
+    /// Decodes a 2-byte output report.
+    pub fn from_report(data: [u8; 2]) -> PanelOutput {
+        let led = if data[0] & 0x10 != 0 {
+            Led::Blink
+        } else if data[0] & 0x80 != 0 {
+            Led::On
+        } else {
+            Led::Off
+        };
+
+        PanelOutput {
+            led,
+            backlight: data[0] & 0x40 != 0,
+            click: data[0] & 0x0f != 0,
+            sound: data[1],
+        }
+    }
+
+    /// Encodes the panel state as an output report.
+    pub fn to_report(&self) -> [u8; 2] {
+        let led = match self.led {
+            Led::Off => 0x00,
+            Led::On => 0x80,
+            Led::Blink => 0x90,
+        };
+        let backlight = if self.backlight { 0x40 } else { 0x00 };
+        let click = if self.click { 0x01 } else { 0x00 };
+
+        [led | backlight | click, self.sound]
+    }
+}
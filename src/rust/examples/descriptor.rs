@@ -0,0 +1,58 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+extern crate beolyd5_controller;
+
+use beolyd5_controller::Beolyd5Controller;
+use std::error::Error;
+
+const USAGE: &str = "usage: descriptor
+       descriptor send <report id> <hex bytes>    e.g. `descriptor send 1 00ff`";
+
+// Prints the reports the panel declares and the contents of its feature reports
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut controller = Beolyd5Controller::new();
+    // Show the descriptor even if it is not what the controller expects
+    controller.set_check_descriptor(false);
+    controller.open()?;
+
+    match args {
+        [] => (),
+        [command, id, data] if command == "send" => {
+            let data = (0..data.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(data.get(i..i + 2).unwrap_or("?"), 16))
+                .collect::<Result<Vec<u8>, _>>()?;
+            controller.send_feature_report(id.parse()?, &data)?;
+        }
+        _ => return Err(USAGE.into()),
+    }
+
+    let descriptor = controller.report_descriptor()?;
+    for report in &descriptor.reports {
+        println!("{} report {}: {} bytes", report.kind, report.id, report.len);
+    }
+    if let Err(err) = descriptor.check() {
+        println!("{}", err);
+    }
+
+    for report in descriptor.feature_reports() {
+        match controller.get_feature_report(report.id, report.len) {
+            Ok(data) => println!("feature report {}: {:02x?}", report.id, data),
+            Err(err) => println!("feature report {}: {}", report.id, err),
+        }
+    }
+
+    controller.close();
+    Ok(())
+}
@@ -0,0 +1,47 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+extern crate beolyd5_controller;
+
+use beolyd5_controller::explore::SweepConfig;
+use beolyd5_controller::Beolyd5Controller;
+use std::time::Duration;
+
+fn main() {
+    let mut controller = Beolyd5Controller::new();
+    controller.enable_exploration();
+
+    if let Err(err) = controller.open() {
+        eprintln!("Failed to open device: {:?}", err);
+        return;
+    }
+
+    // Play with the panel for a while, then see which unknown bits moved
+    println!("Recording input reports for 30 seconds, use the wheels and buttons...");
+    std::thread::sleep(Duration::from_secs(30));
+
+    let report = controller.exploration_report().unwrap_or_default();
+    println!("{} reports seen", report.reports);
+    for bit in report.unknown_bits() {
+        println!(
+            "byte {} bit {}: changed {} times, set {} times, alongside {:?}",
+            bit.byte, bit.bit, bit.changes, bit.set, bit.co_events
+        );
+    }
+
+    // Sweep the second output byte with the backlight on
+    let config = SweepConfig::default();
+    let result = controller.sweep_output(&config, |step| {
+        println!(
+            "sent {:02x?}: {} reports, changed bits {:02x?}",
+            step.output, step.reports, step.changed_bits
+        );
+    });
+    if let Err(err) = result {
+        eprintln!("Sweep failed: {:?}", err);
+    }
+
+    controller.close();
+}
@@ -0,0 +1,52 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+extern crate beolyd5_controller;
+
+use beolyd5_controller::Beolyd5Controller;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+
+// Serves the controller metrics for Prometheus to scrape, e.g.
+// `cargo run --example metrics -- 0.0.0.0:9105` and point a scrape job at http://<pi>:9105/metrics
+fn main() {
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:9105".to_string());
+
+    let mut controller = Beolyd5Controller::new();
+    if let Err(err) = controller.open() {
+        eprintln!("Failed to open device: {:?}", err);
+        return;
+    }
+
+    let listener = match TcpListener::bind(&address) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Failed to listen on {}: {:?}", address, err);
+            return;
+        }
+    };
+    println!("Serving metrics on http://{}/metrics", address);
+
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue };
+
+        // Every request gets the metrics, just read past the request headers
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).map(|n| n > 0).unwrap_or(false) && line.trim() != "" {
+            line.clear();
+        }
+
+        let body = controller.metrics().to_prometheus();
+        let _ = write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+    }
+}
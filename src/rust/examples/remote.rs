@@ -0,0 +1,79 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+extern crate beolyd5_controller;
+
+use beolyd5_controller::transport;
+use beolyd5_controller::transport::remote::{self, RemoteTransport, DEFAULT_PORT};
+use beolyd5_controller::types::{Button, Event};
+use beolyd5_controller::Beolyd5Controller;
+use std::error::Error;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+const USAGE: &str =
+    "usage: remote serve [address]    forward the local panel, e.g. `remote serve 0.0.0.0:9106`
+       remote connect <address>   print the events of a forwarded panel and click on every press";
+
+// Forwards a panel over TCP, or uses a forwarded one
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    match args {
+        [command] if command == "serve" => serve(&format!("0.0.0.0:{}", DEFAULT_PORT)),
+        [command, address] if command == "serve" => serve(address),
+        [command, address] if command == "connect" => connect(address),
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn serve(address: &str) -> Result<(), Box<dyn Error>> {
+    let panel = transport::open_default(0x0cd4, 0x1112)?;
+    let listener = TcpListener::bind(address)?;
+    println!("Forwarding the panel on {}", listener.local_addr()?);
+    remote::serve(listener, panel)?;
+    Ok(())
+}
+
+fn connect(address: &str) -> Result<(), Box<dyn Error>> {
+    let mut controller = Beolyd5Controller::with_transport(RemoteTransport::connect(address)?);
+    let clicker = Arc::new(Mutex::new(None::<Beolyd5Controller>));
+    let clicker_clone = clicker.clone();
+
+    controller.register_event_callback(Arc::new(Mutex::new(
+        move |event: Event| -> Result<(), Box<dyn Error + Send>> {
+            println!("{:?}", event);
+            if let (Event::ButtonPressed(button), Some(controller)) =
+                (event, clicker_clone.lock().unwrap().as_ref())
+            {
+                if button != Button::None {
+                    let _ = controller.click();
+                }
+            }
+            Ok(())
+        },
+    )));
+
+    controller.open()?;
+    println!(
+        "Connected to {}, panel {}",
+        address,
+        controller
+            .serial_number()
+            .as_deref()
+            .unwrap_or("without serial number")
+    );
+    *clicker.lock().unwrap() = Some(controller.clone());
+
+    loop {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,84 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+extern crate beolyd5_controller;
+
+use beolyd5_controller::settings::ControllerSettings;
+use beolyd5_controller::Beolyd5Controller;
+use std::error::Error;
+
+const USAGE: &str = "usage: settings show
+       settings set <key> <value>    e.g. `settings set front_wheel.sensitivity 1.5`";
+
+// Shows or changes the settings of the connected panel, saved under its serial number
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut controller = Beolyd5Controller::new();
+    controller.open()?;
+    controller.close();
+    println!(
+        "# Panel {}",
+        controller
+            .serial_number()
+            .as_deref()
+            .unwrap_or("without serial number")
+    );
+
+    match args {
+        [command] if command == "show" => (),
+        [command, key, value] if command == "set" => {
+            let settings = set(controller.settings(), key, value)?;
+            controller.set_settings(settings);
+            controller.save_settings()?;
+        }
+        _ => return Err(USAGE.into()),
+    }
+
+    print!("{}", toml::to_string_pretty(&controller.settings())?);
+    Ok(())
+}
+
+/// Sets the dotted `key` to `value`, parsed as a TOML value.
+fn set(
+    settings: ControllerSettings,
+    key: &str,
+    value: &str,
+) -> Result<ControllerSettings, Box<dyn Error>> {
+    let mut root = toml::Value::try_from(settings)?;
+    let mut value: toml::Value =
+        toml::from_str::<toml::Table>(&format!("value = {}", value))?["value"].clone();
+
+    let (groups, name) = key
+        .rsplit_once('.')
+        .map_or((None, key), |(g, n)| (Some(g), n));
+    let mut table = root.as_table_mut().ok_or("settings are not a table")?;
+    for group in groups.into_iter().flat_map(|g| g.split('.')) {
+        table = table
+            .get_mut(group)
+            .and_then(toml::Value::as_table_mut)
+            .ok_or_else(|| format!("unknown setting {}", key))?;
+    }
+
+    // Allow `1` for settings like `sensitivity = 1.0`
+    if let (Some(toml::Value::Float(_)), toml::Value::Integer(i)) = (table.get(name), &value) {
+        value = toml::Value::Float(*i as f64);
+    }
+    table.insert(name.to_string(), value.clone());
+
+    // Unknown keys are ignored when reading settings, so check that the value made it
+    let settings: ControllerSettings = root.try_into()?;
+    let stored = toml::Value::try_from(settings)?;
+    if key.split('.').try_fold(&stored, |v, part| v.get(part)) != Some(&value) {
+        return Err(format!("unknown setting {}", key).into());
+    }
+    Ok(settings)
+}
@@ -0,0 +1,49 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+extern crate beolyd5_controller;
+
+use beolyd5_controller::types::{Button, Wheel};
+use beolyd5_controller::virtual_panel::{tui, VirtualPanel};
+use beolyd5_controller::Beolyd5Controller;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+fn main() {
+    let panel = VirtualPanel::new();
+    let mut controller = Beolyd5Controller::with_transport(panel.transport().unwrap());
+
+    let panel_clone = panel.clone();
+    controller.register_wheel_event_callback(Arc::new(Mutex::new(
+        move |(wheel, pos): (Wheel, u8)| -> Result<(), Box<dyn Error + Send>> {
+            panel_clone.note(format!("WheelEvent: {:?} at position {}", wheel, pos));
+
+            Ok(())
+        },
+    )));
+
+    let panel_clone = panel.clone();
+    controller.register_button_event_callback(Arc::new(Mutex::new(
+        move |button: Button| -> Result<(), Box<dyn Error + Send>> {
+            panel_clone.note(format!("ButtonEvent: {:?}", button));
+
+            Ok(())
+        },
+    )));
+
+    if let Err(err) = controller.open() {
+        eprintln!("Failed to open virtual device: {:?}", err);
+        return;
+    }
+
+    // Light up the panel, so the indicators have something to show
+    controller.send([0x40, 0x00]).unwrap();
+
+    if let Err(err) = tui::run(&panel) {
+        eprintln!("Terminal error: {:?}", err);
+    }
+
+    controller.close();
+}
@@ -0,0 +1,16 @@
+# Copyright (c) 2024. Lars Baunwall. All rights reserved.
+# Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+
+import beolyd5
+
+controller = beolyd5.Controller()
+controller.open()
+controller.set_backlight(True)
+controller.set_led_pattern({"steps": [{"led": "On", "millis": 1000}, {"led": "Off", "millis": 1000}]})
+
+for event in controller:
+    print(event)
+    if "ButtonPressed" in event:
+        controller.click()
+        if event["ButtonPressed"] == "Standby":
+            controller.close()
@@ -0,0 +1,301 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Python bindings for `beolyd5_controller`.
+//!
+//! Events, settings, metrics and LED patterns cross the boundary as plain Python values built from
+//! their serde representation, e.g. `{"WheelMoved": ["Front", 1]}` or `{"ButtonPressed": "Go"}`.
+
+// The code generated by `#[pymethods]` converts `PyResult`s in a way clippy considers useless
+#![allow(clippy::useless_conversion)]
+
+use beolyd5_controller::decoder::{self, DecoderState, REPORT_LEN};
+use beolyd5_controller::led::LedPattern;
+use beolyd5_controller::settings::ControllerSettings;
+use beolyd5_controller::types::{Event, Led};
+use beolyd5_controller::Beolyd5Controller;
+use pyo3::exceptions::{PyOSError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyList, PyTuple};
+use serde_json::Value;
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long `__next__` waits before checking for Ctrl+C and a closed controller.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Controller is an open or closed BeoSound 5 panel.
+///
+/// Iterating a controller yields the decoded events until it is closed.
+#[pyclass]
+struct Controller {
+    inner: Beolyd5Controller,
+    events: Mutex<Receiver<Event>>,
+}
+
+#[pymethods]
+impl Controller {
+    #[new]
+    fn new() -> Controller {
+        let mut inner = Beolyd5Controller::new();
+        let (sender, events) = mpsc::channel();
+        inner.register_event_callback(Arc::new(Mutex::new(
+            move |event: Event| -> Result<(), Box<dyn Error + Send>> {
+                // Nobody listening any more is fine
+                let _ = sender.send(event);
+                Ok(())
+            },
+        )));
+
+        Controller {
+            inner,
+            events: Mutex::new(events),
+        }
+    }
+
+    /// Opens the panel and starts reading events. Raises `OSError` if it cannot be opened.
+    fn open(&mut self, py: Python<'_>) -> PyResult<()> {
+        let inner = &mut self.inner;
+        py.allow_threads(|| inner.open().map_err(|e| e.to_string()))
+            .map_err(PyOSError::new_err)
+    }
+
+    /// Stops reading events. Iteration ends once the queued events are consumed.
+    fn close(&self) {
+        self.inner.close();
+    }
+
+    fn is_open(&self) -> bool {
+        self.inner.is_open()
+    }
+
+    /// The serial number of the opened panel, or `None`.
+    fn serial_number(&self) -> Option<String> {
+        self.inner.serial_number()
+    }
+
+    /// Waits up to `timeout` seconds (forever if `None`) for the next event.
+    /// Returns `None` when the timeout expired.
+    #[pyo3(signature = (timeout=None))]
+    fn next_event(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<Option<PyObject>> {
+        let timeout = timeout
+            .map(Duration::try_from_secs_f64)
+            .transpose()
+            .map_err(|_| {
+                PyValueError::new_err("timeout must be a non-negative number of seconds")
+            })?;
+        let started = std::time::Instant::now();
+        loop {
+            let wait = match timeout {
+                Some(timeout) => timeout.saturating_sub(started.elapsed()).min(POLL_INTERVAL),
+                None => POLL_INTERVAL,
+            };
+            match py.allow_threads(|| self.events.lock().unwrap().recv_timeout(wait)) {
+                Ok(event) => return to_py(py, &event).map(Some),
+                Err(RecvTimeoutError::Disconnected) => return Ok(None),
+                Err(RecvTimeoutError::Timeout) => {
+                    py.check_signals()?;
+                    if timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        loop {
+            if let Some(event) = self.next_event(py, Some(POLL_INTERVAL.as_secs_f64()))? {
+                return Ok(Some(event));
+            }
+            if !self.inner.is_open() {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Sends a raw 2-byte output report.
+    fn send(&self, py: Python<'_>, byte0: u8, byte1: u8) -> PyResult<()> {
+        py.allow_threads(|| self.inner.send([byte0, byte1]).map_err(|e| e.to_string()))
+            .map_err(PyOSError::new_err)
+    }
+
+    fn tick(&self, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| self.inner.tick().map_err(|e| e.to_string()))
+            .map_err(PyOSError::new_err)
+    }
+
+    fn click(&self, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| self.inner.click().map_err(|e| e.to_string()))
+            .map_err(PyOSError::new_err)
+    }
+
+    fn play_sound(&self, py: Python<'_>, code: u8) -> PyResult<()> {
+        py.allow_threads(|| self.inner.play_sound(code).map_err(|e| e.to_string()))
+            .map_err(PyOSError::new_err)
+    }
+
+    /// Switches the LED to `"On"`, `"Off"` or `"Blink"`.
+    fn set_led(&self, py: Python<'_>, led: &Bound<'_, PyAny>) -> PyResult<()> {
+        let led: Led = from_py(led)?;
+        py.allow_threads(|| self.inner.set_led(led).map_err(|e| e.to_string()))
+            .map_err(PyOSError::new_err)
+    }
+
+    fn set_backlight(&self, py: Python<'_>, on: bool) -> PyResult<()> {
+        py.allow_threads(|| self.inner.set_backlight(on).map_err(|e| e.to_string()))
+            .map_err(PyOSError::new_err)
+    }
+
+    /// Sets the ambient LED pattern, e.g. `{"steps": [{"led": "On", "millis": 500}, {"led": "Off", "millis": 500}], "repeat": None}`.
+    fn set_led_pattern(&self, pattern: &Bound<'_, PyAny>) -> PyResult<()> {
+        let pattern: LedPattern = from_py(pattern)?;
+        self.inner.set_led_pattern(pattern);
+        Ok(())
+    }
+
+    /// Plays an LED pattern over the ambient one, see `set_led_pattern`.
+    #[pyo3(signature = (pattern, priority=0))]
+    fn notify_led(&self, pattern: &Bound<'_, PyAny>, priority: u8) -> PyResult<()> {
+        let pattern: LedPattern = from_py(pattern)?;
+        self.inner.notify_led(pattern, priority);
+        Ok(())
+    }
+
+    fn clear_led_notifications(&self) {
+        self.inner.clear_led_notifications();
+    }
+
+    fn settings(&self, py: Python<'_>) -> PyResult<PyObject> {
+        to_py(py, &self.inner.settings())
+    }
+
+    fn set_settings(&self, settings: &Bound<'_, PyAny>) -> PyResult<()> {
+        let settings: ControllerSettings = from_py(settings)?;
+        self.inner.set_settings(settings);
+        Ok(())
+    }
+
+    fn save_settings(&self) -> PyResult<()> {
+        self.inner
+            .save_settings()
+            .map_err(|e| PyOSError::new_err(e.to_string()))
+    }
+
+    fn metrics(&self, py: Python<'_>) -> PyResult<PyObject> {
+        to_py(py, &self.inner.metrics())
+    }
+}
+
+/// Decoder turns raw 6-byte input reports into events, exactly like the controller does.
+#[pyclass]
+#[derive(Default)]
+struct Decoder {
+    state: DecoderState,
+}
+
+#[pymethods]
+impl Decoder {
+    #[new]
+    fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    /// Decodes `report` against the reports decoded before and returns the list of events.
+    fn decode(&mut self, py: Python<'_>, report: &[u8]) -> PyResult<PyObject> {
+        let report: [u8; REPORT_LEN] = report
+            .try_into()
+            .map_err(|_| PyValueError::new_err(format!("a report is {} bytes", REPORT_LEN)))?;
+        let (events, state) = decoder::decode(report, &self.state);
+        self.state = state;
+        to_py(py, &events)
+    }
+}
+
+fn to_py<T: serde::Serialize>(py: Python<'_>, value: &T) -> PyResult<PyObject> {
+    let value = serde_json::to_value(value).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    json_to_py(py, &value)
+}
+
+fn from_py<T: serde::de::DeserializeOwned>(obj: &Bound<'_, PyAny>) -> PyResult<T> {
+    serde_json::from_value(py_to_json(obj)?).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+fn json_to_py(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.into_py(py),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => i.into_py(py),
+            (_, Some(u)) => u.into_py(py),
+            _ => n.as_f64().unwrap_or(f64::NAN).into_py(py),
+        },
+        Value::String(s) => s.into_py(py),
+        Value::Array(items) => {
+            let items = items
+                .iter()
+                .map(|v| json_to_py(py, v))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new_bound(py, items).into_py(py)
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new_bound(py);
+            for (key, value) in map {
+                dict.set_item(key, json_to_py(py, value)?)?;
+            }
+            dict.into_py(py)
+        }
+    })
+}
+
+fn py_to_json(obj: &Bound<'_, PyAny>) -> PyResult<Value> {
+    if obj.is_none() {
+        Ok(Value::Null)
+    } else if let Ok(b) = obj.downcast::<PyBool>() {
+        Ok(Value::Bool(b.is_true()))
+    } else if let Ok(i) = obj.extract::<i64>() {
+        Ok(Value::from(i))
+    } else if let Ok(f) = obj.extract::<f64>() {
+        Ok(Value::from(f))
+    } else if let Ok(s) = obj.extract::<String>() {
+        Ok(Value::String(s))
+    } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        let mut map = serde_json::Map::new();
+        for (key, value) in dict.iter() {
+            map.insert(key.extract::<String>()?, py_to_json(&value)?);
+        }
+        Ok(Value::Object(map))
+    } else if let Ok(list) = obj.downcast::<PyList>() {
+        list.iter()
+            .map(|v| py_to_json(&v))
+            .collect::<PyResult<Vec<_>>>()
+            .map(Value::Array)
+    } else if let Ok(tuple) = obj.downcast::<PyTuple>() {
+        tuple
+            .iter()
+            .map(|v| py_to_json(&v))
+            .collect::<PyResult<Vec<_>>>()
+            .map(Value::Array)
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "cannot convert {} to a controller value",
+            obj.get_type()
+        )))
+    }
+}
+
+/// Python bindings for the BeoSound 5 controller.
+#[pymodule]
+fn beolyd5(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Controller>()?;
+    m.add_class::<Decoder>()?;
+    Ok(())
+}
@@ -0,0 +1,44 @@
+# Copyright (c) 2024. Lars Baunwall. All rights reserved.
+# Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+
+import unittest
+
+import beolyd5
+
+
+class DecoderTest(unittest.TestCase):
+    def test_wheel_and_buttons(self):
+        decoder = beolyd5.Decoder()
+
+        self.assertEqual(decoder.decode(bytes([0x01, 0, 0, 0, 0, 0])), [{"WheelMoved": ["Front", 1]}])
+        self.assertEqual(decoder.decode(bytes([0, 0, 0, 0x40, 0, 0])), [{"ButtonPressed": "Go"}])
+        self.assertEqual(decoder.decode(bytes([0, 0, 0, 0, 0, 0])), [{"ButtonReleased": "Go"}])
+
+    def test_rejects_short_reports(self):
+        with self.assertRaises(ValueError):
+            beolyd5.Decoder().decode(b"\x00")
+
+
+class ControllerTest(unittest.TestCase):
+    def test_settings_round_trip(self):
+        controller = beolyd5.Controller()
+        settings = controller.settings()
+        settings["front_wheel"]["sensitivity"] = 2.5
+        settings["idle_timeout_secs"] = 60
+
+        controller.set_settings(settings)
+        self.assertEqual(controller.settings(), settings)
+
+    def test_rejects_invalid_timeouts(self):
+        controller = beolyd5.Controller()
+        for timeout in (-1.0, float("nan")):
+            with self.assertRaises(ValueError):
+                controller.next_event(timeout)
+
+    def test_rejects_unknown_led(self):
+        with self.assertRaises(ValueError):
+            beolyd5.Controller().set_led_pattern({"steps": [{"led": "Purple", "millis": 1}]})
+
+
+if __name__ == "__main__":
+    unittest.main()
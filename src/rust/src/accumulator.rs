@@ -0,0 +1,313 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Absolute values from the relative front and back wheels.
+//!
+//! The front and back wheels only report how far they moved. A [`WheelAccumulator`] keeps a
+//! running total, e.g. for a volume knob or a list position, bounded by a minimum and maximum
+//! that it either stops at or wraps around.
+
+use std::io;
+
+/// `Bounds` decides what happens when the value passes the minimum or maximum.
+#[derive(Debug, Copy, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub enum Bounds {
+    /// Stop at the minimum and maximum.
+    #[default]
+    Clamp,
+    /// Continue from the other end.
+    Wrap,
+}
+
+/// `Boundary` is one end of the accumulator range.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Boundary {
+    Min,
+    Max,
+}
+
+/// `AccumulatorEvent` is a change reported by a [`WheelAccumulator`].
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum AccumulatorEvent {
+    /// The value changed.
+    ValueChanged(f64),
+    /// The wheel pushed against a boundary while clamping.
+    BoundaryReached(Boundary),
+    /// The value wrapped past a boundary to the other end.
+    Wrapped(Boundary),
+}
+
+/// `AccumulatorConfig` describes the range and feel of a [`WheelAccumulator`].
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AccumulatorConfig {
+    /// How much the value changes per wheel step.
+    pub step: f64,
+    pub min: f64,
+    pub max: f64,
+    pub bounds: Bounds,
+    /// Snap the reported value to multiples of this size (counted from `min`), or `None` for no snapping.
+    pub detent: Option<f64>,
+    /// The value to start from.
+    pub initial: f64,
+}
+
+impl Default for AccumulatorConfig {
+    fn default() -> Self {
+        AccumulatorConfig {
+            step: 1.0,
+            min: 0.0,
+            max: 100.0,
+            bounds: Bounds::Clamp,
+            detent: None,
+            initial: 0.0,
+        }
+    }
+}
+
+/// `WheelAccumulator` turns relative wheel movement into a bounded or wrapping absolute value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WheelAccumulator {
+    config: AccumulatorConfig,
+    raw: f64,
+    value: f64,
+}
+
+impl AccumulatorConfig {
+    /// Checks that the range is usable. Returns an `ErrorKind::InvalidInput` error if `min` is
+    /// above `max` or either is not a number.
+    pub fn validate(&self) -> io::Result<()> {
+        if self.min.is_nan() || self.max.is_nan() || self.min > self.max {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("accumulator min {} is above max {}", self.min, self.max),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for WheelAccumulator {
+    fn default() -> Self {
+        Self::new(AccumulatorConfig::default()).expect("the default config is valid")
+    }
+}
+
+impl WheelAccumulator {
+    /// Creates a new accumulator starting at `config.initial`, brought into range.
+    /// Fails if the config does not [validate](AccumulatorConfig::validate).
+    pub fn new(config: AccumulatorConfig) -> io::Result<WheelAccumulator> {
+        config.validate()?;
+        let mut accumulator = WheelAccumulator {
+            config,
+            raw: 0.0,
+            value: 0.0,
+        };
+        accumulator.set(config.initial);
+        Ok(accumulator)
+    }
+
+    /// Returns the current value, snapped to the detent if configured.
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Returns the configuration.
+    pub fn config(&self) -> &AccumulatorConfig {
+        &self.config
+    }
+
+    /// Sets the value directly, e.g. when the volume was changed elsewhere. No events are emitted.
+    pub fn set(&mut self, value: f64) {
+        self.raw = match self.config.bounds {
+            Bounds::Clamp => value.clamp(self.config.min, self.config.max),
+            Bounds::Wrap => self.wrap(value).0,
+        };
+        self.value = self.snap(self.raw);
+    }
+
+    /// Feeds a wheel event position as reported for the front or back wheel.
+    pub fn spin(&mut self, pos: u8) -> Vec<AccumulatorEvent> {
+        self.spin_by(relative_movement(pos) as f64)
+    }
+
+    /// Moves the value by `steps` wheel steps, negative steps moving towards the minimum.
+    pub fn spin_by(&mut self, steps: f64) -> Vec<AccumulatorEvent> {
+        let mut events = Vec::new();
+        let target = self.raw + steps * self.config.step;
+
+        match self.config.bounds {
+            Bounds::Clamp => {
+                self.raw = target.clamp(self.config.min, self.config.max);
+                if steps < 0.0 && target <= self.config.min {
+                    events.push(AccumulatorEvent::BoundaryReached(Boundary::Min));
+                } else if steps > 0.0 && target >= self.config.max {
+                    events.push(AccumulatorEvent::BoundaryReached(Boundary::Max));
+                }
+            }
+            Bounds::Wrap => {
+                let (wrapped, boundary) = self.wrap(target);
+                self.raw = wrapped;
+                if let Some(boundary) = boundary {
+                    events.push(AccumulatorEvent::Wrapped(boundary));
+                }
+            }
+        }
+
+        let value = self.snap(self.raw);
+        if value != self.value {
+            self.value = value;
+            events.insert(0, AccumulatorEvent::ValueChanged(value));
+        }
+
+        events
+    }
+
+    /// Wraps `value` into `[min, max)`, returning the boundary that was crossed, if any.
+    fn wrap(&self, value: f64) -> (f64, Option<Boundary>) {
+        let (min, max) = (self.config.min, self.config.max);
+        let span = max - min;
+        if span <= 0.0 {
+            return (min, None);
+        }
+
+        let wrapped = (value - min).rem_euclid(span) + min;
+        let boundary = if value >= max {
+            Some(Boundary::Max)
+        } else if value < min {
+            Some(Boundary::Min)
+        } else {
+            None
+        };
+        (wrapped, boundary)
+    }
+
+    fn snap(&self, raw: f64) -> f64 {
+        match self.config.detent {
+            Some(detent) if detent > 0.0 => {
+                let snapped = ((raw - self.config.min) / detent).round() * detent + self.config.min;
+                // Rounding up may land on `max`, which is the other end when wrapping
+                match self.config.bounds {
+                    Bounds::Clamp => snapped.clamp(self.config.min, self.config.max),
+                    Bounds::Wrap => self.wrap(snapped).0,
+                }
+            }
+            _ => raw,
+        }
+    }
+}
+
+/// Interprets a front or back wheel position as a signed step count.
+/// Positions up to `0x7f` are clockwise, the rest counter-clockwise (`0xff` is one step back).
+pub fn relative_movement(pos: u8) -> i8 {
+    pos as i8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamps_and_reports_boundaries() {
+        let mut volume = WheelAccumulator::new(AccumulatorConfig {
+            step: 5.0,
+            initial: 90.0,
+            ..AccumulatorConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            volume.spin(0x01),
+            vec![AccumulatorEvent::ValueChanged(95.0)]
+        );
+        assert_eq!(
+            volume.spin(0x02),
+            vec![
+                AccumulatorEvent::ValueChanged(100.0),
+                AccumulatorEvent::BoundaryReached(Boundary::Max)
+            ]
+        );
+        assert_eq!(
+            volume.spin(0x01),
+            vec![AccumulatorEvent::BoundaryReached(Boundary::Max)]
+        );
+        assert_eq!(
+            volume.spin(0xff),
+            vec![AccumulatorEvent::ValueChanged(95.0)]
+        );
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut list = WheelAccumulator::new(AccumulatorConfig {
+            max: 10.0,
+            bounds: Bounds::Wrap,
+            ..AccumulatorConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            list.spin(0xff),
+            vec![
+                AccumulatorEvent::ValueChanged(9.0),
+                AccumulatorEvent::Wrapped(Boundary::Min)
+            ]
+        );
+        assert_eq!(
+            list.spin_by(1.0),
+            vec![
+                AccumulatorEvent::ValueChanged(0.0),
+                AccumulatorEvent::Wrapped(Boundary::Max)
+            ]
+        );
+    }
+
+    #[test]
+    fn snaps_to_detents() {
+        let mut knob = WheelAccumulator::new(AccumulatorConfig {
+            step: 0.25,
+            detent: Some(1.0),
+            ..AccumulatorConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(knob.spin(0x01), vec![]);
+        assert_eq!(knob.spin(0x01), vec![AccumulatorEvent::ValueChanged(1.0)]);
+        assert_eq!(knob.value(), 1.0);
+        assert_eq!(knob.spin(0x02), vec![]);
+        assert_eq!(knob.spin(0x02), vec![AccumulatorEvent::ValueChanged(2.0)]);
+    }
+
+    #[test]
+    fn snapping_stays_in_the_wrapped_range() {
+        let mut list = WheelAccumulator::new(AccumulatorConfig {
+            step: 0.2,
+            max: 10.0,
+            bounds: Bounds::Wrap,
+            detent: Some(1.0),
+            initial: 9.6,
+            ..AccumulatorConfig::default()
+        })
+        .unwrap();
+        assert_eq!(list.value(), 0.0);
+
+        list.set(8.6);
+        assert_eq!(list.value(), 9.0);
+        assert_eq!(
+            list.spin_by(-2.0),
+            vec![AccumulatorEvent::ValueChanged(8.0)]
+        );
+    }
+
+    #[test]
+    fn rejects_inverted_ranges() {
+        let config = AccumulatorConfig {
+            min: 10.0,
+            max: 0.0,
+            ..AccumulatorConfig::default()
+        };
+        let err = WheelAccumulator::new(config).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,241 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Selection along the arc, driven by the angular wheel.
+//!
+//! The items of an [`ArcMenu`] are spread evenly over a range of angular wheel positions. Moving
+//! the pointer highlights the item under it, and a [hysteresis](ArcMenuConfig::hysteresis) keeps
+//! the highlight from flickering when the pointer rests on the border between two items.
+
+use crate::decoder::ANGULAR_MAX;
+use crate::types::{Button, Event, Wheel};
+
+/// `ArcMenuConfig` describes where the items sit on the arc and how the menu reacts.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ArcMenuConfig {
+    /// Angular wheel position where the first item starts.
+    pub start: u8,
+    /// Angular wheel position where the last item ends (inclusive).
+    pub end: u8,
+    /// How many positions the pointer must move past the border of the highlighted item before
+    /// the neighbouring item is highlighted.
+    pub hysteresis: u8,
+    /// Emit [`ArcMenuEvent::Click`] whenever the highlight moves to another item.
+    pub click_on_change: bool,
+}
+
+impl Default for ArcMenuConfig {
+    fn default() -> Self {
+        ArcMenuConfig {
+            start: 0,
+            end: ANGULAR_MAX,
+            hysteresis: 1,
+            click_on_change: false,
+        }
+    }
+}
+
+/// `ArcMenuEvent` is a change produced by an [`ArcMenu`].
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum ArcMenuEvent {
+    /// The highlight moved from one item to another, by index.
+    HighlightChanged {
+        previous: Option<usize>,
+        current: Option<usize>,
+    },
+    /// The highlighted item was selected.
+    Selected(usize),
+    /// Time to play a click, see [`ArcMenuConfig::click_on_change`].
+    Click,
+}
+
+/// `ArcMenu` maps the angular wheel position to one of its items.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArcMenu<T> {
+    items: Vec<T>,
+    config: ArcMenuConfig,
+    highlighted: Option<usize>,
+}
+
+impl<T> ArcMenu<T> {
+    /// Creates a menu with nothing highlighted until the first pointer position arrives.
+    /// A `start` above `end` is swapped, so the range always runs from the lower position.
+    pub fn new(items: Vec<T>, mut config: ArcMenuConfig) -> ArcMenu<T> {
+        if config.start > config.end {
+            std::mem::swap(&mut config.start, &mut config.end);
+        }
+        ArcMenu {
+            items,
+            config,
+            highlighted: None,
+        }
+    }
+
+    /// Returns the items of the menu.
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Replaces the items. Nothing is highlighted until the next pointer position arrives.
+    pub fn set_items(&mut self, items: Vec<T>) -> Vec<ArcMenuEvent> {
+        self.items = items;
+        self.highlight(None)
+    }
+
+    /// Returns the index and item currently highlighted.
+    pub fn highlighted(&self) -> Option<(usize, &T)> {
+        self.highlighted.map(|i| (i, &self.items[i]))
+    }
+
+    /// Feeds a decoded controller event: angular wheel movement moves the highlight, and the Go
+    /// button selects the highlighted item. Other events are ignored.
+    pub fn handle(&mut self, event: Event) -> Vec<ArcMenuEvent> {
+        match event {
+            Event::WheelMoved(Wheel::Angular, pos) => self.update(pos),
+            Event::ButtonPressed(Button::Go) => self.select().into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Moves the pointer to the angular wheel position `pos`.
+    pub fn update(&mut self, pos: u8) -> Vec<ArcMenuEvent> {
+        if self.items.is_empty() {
+            return self.highlight(None);
+        }
+
+        let index = match self.highlighted {
+            Some(current) if self.within(current, pos) => current,
+            _ => self.index_at(pos),
+        };
+        self.highlight(Some(index))
+    }
+
+    /// Selects the highlighted item, if any.
+    pub fn select(&self) -> Option<ArcMenuEvent> {
+        self.highlighted.map(ArcMenuEvent::Selected)
+    }
+
+    fn highlight(&mut self, index: Option<usize>) -> Vec<ArcMenuEvent> {
+        if index == self.highlighted {
+            return Vec::new();
+        }
+
+        let mut events = vec![ArcMenuEvent::HighlightChanged {
+            previous: self.highlighted,
+            current: index,
+        }];
+        if self.config.click_on_change && index.is_some() {
+            events.push(ArcMenuEvent::Click);
+        }
+        self.highlighted = index;
+        events
+    }
+
+    /// Width of an item in wheel positions.
+    fn item_width(&self) -> f64 {
+        let span = self.config.end.saturating_sub(self.config.start) as f64 + 1.0;
+        span / self.items.len() as f64
+    }
+
+    /// Index of the item under `pos`, positions outside the range mapping to the first or last item.
+    fn index_at(&self, pos: u8) -> usize {
+        let offset = pos.clamp(self.config.start, self.config.end) - self.config.start;
+        ((offset as f64 / self.item_width()) as usize).min(self.items.len() - 1)
+    }
+
+    /// Whether `pos` is still on item `index`, extended by the hysteresis on both sides.
+    fn within(&self, index: usize, pos: u8) -> bool {
+        let width = self.item_width();
+        let start = self.config.start as f64 + index as f64 * width - self.config.hysteresis as f64;
+        let end =
+            self.config.start as f64 + (index + 1) as f64 * width + self.config.hysteresis as f64;
+        let pos = pos.clamp(self.config.start, self.config.end) as f64;
+        pos >= start && pos < end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn menu(click_on_change: bool) -> ArcMenu<&'static str> {
+        ArcMenu::new(
+            vec!["Radio", "Music", "Settings"],
+            ArcMenuConfig {
+                start: 0,
+                end: 119,
+                hysteresis: 3,
+                click_on_change,
+            },
+        )
+    }
+
+    #[test]
+    fn highlights_item_under_pointer() {
+        let mut menu = menu(false);
+
+        assert_eq!(
+            menu.update(50),
+            vec![ArcMenuEvent::HighlightChanged {
+                previous: None,
+                current: Some(1)
+            }]
+        );
+        assert_eq!(menu.highlighted(), Some((1, &"Music")));
+        assert_eq!(menu.update(55), vec![]);
+        assert_eq!(
+            menu.update(200),
+            vec![ArcMenuEvent::HighlightChanged {
+                previous: Some(1),
+                current: Some(2)
+            }]
+        );
+    }
+
+    #[test]
+    fn hysteresis_keeps_highlight_at_borders() {
+        let mut menu = menu(false);
+        menu.update(45);
+
+        // Item 1 spans 40..80, the neighbour only wins 3 positions past the border
+        assert_eq!(menu.update(38), vec![]);
+        assert_eq!(menu.update(82), vec![]);
+        assert_eq!(menu.update(83).len(), 1);
+        assert_eq!(menu.highlighted(), Some((2, &"Settings")));
+    }
+
+    #[test]
+    fn clicks_and_selects() {
+        let mut menu = menu(true);
+
+        assert_eq!(
+            menu.handle(Event::WheelMoved(Wheel::Angular, 0)).last(),
+            Some(&ArcMenuEvent::Click)
+        );
+        assert_eq!(
+            menu.handle(Event::ButtonPressed(Button::Go)),
+            vec![ArcMenuEvent::Selected(0)]
+        );
+        assert_eq!(menu.handle(Event::WheelMoved(Wheel::Front, 1)), vec![]);
+    }
+
+    #[test]
+    fn swaps_an_inverted_range() {
+        let mut menu = ArcMenu::new(
+            vec!["Radio", "Music"],
+            ArcMenuConfig {
+                start: 100,
+                end: 20,
+                ..ArcMenuConfig::default()
+            },
+        );
+
+        assert_eq!((menu.config.start, menu.config.end), (20, 100));
+        menu.update(0);
+        assert_eq!(menu.highlighted(), Some((0, &"Radio")));
+        menu.update(ANGULAR_MAX);
+        assert_eq!(menu.highlighted(), Some((1, &"Music")));
+    }
+}
@@ -0,0 +1,92 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Time for the timing based parts of the controller.
+//!
+//! The idle timeout, LED patterns and metrics ask a [`Clock`] for the time instead of calling
+//! `Instant::now()`. [`SystemClock`] is the real time; [`ManualClock`] only moves when told to,
+//! so tests can step through timeouts exactly:
+//!
+//! ```
+//! use beolyd5_controller::clock::{Clock, ManualClock};
+//! use std::time::Duration;
+//!
+//! let clock = ManualClock::new();
+//! let start = clock.now();
+//! clock.advance(Duration::from_secs(60));
+//! assert_eq!(clock.now() - start, Duration::from_secs(60));
+//! ```
+//!
+//! Components that are fed events, like [`GestureRecognizer`](crate::gesture::GestureRecognizer)
+//! and [`LedScheduler`](crate::led::LedScheduler), take the time as an argument; pass them
+//! `clock.now()`.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// `Clock` tells the current time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// `SystemClock` is the real, monotonic time.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// `ManualClock` stands still until it is advanced. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    /// Creates a clock stopped at the current time.
+    pub fn new() -> ManualClock {
+        ManualClock {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_only_moves_when_advanced() {
+        let clock = ManualClock::new();
+        let shared = clock.clone();
+        let start = clock.now();
+
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(clock.now(), start);
+
+        shared.advance(Duration::from_millis(250));
+        assert_eq!(clock.now() - start, Duration::from_millis(250));
+    }
+}
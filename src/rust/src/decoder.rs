@@ -0,0 +1,8 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Pure decoding of BeoSound 5 input reports, from [`beolyd5_core::decoder`].
+
+pub use beolyd5_core::decoder::*;
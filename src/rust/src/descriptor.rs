@@ -0,0 +1,269 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! The reports a panel declares in its HID report descriptor.
+//!
+//! The controller assumes a 6-byte input report and a 2-byte output report. [`ReportDescriptor`]
+//! parses what the device actually declares, so [`Beolyd5Controller::open`](crate::Beolyd5Controller::open)
+//! can check it, and lists the feature reports for protocol research.
+
+use crate::decoder::{OUTPUT_REPORT_LEN, REPORT_LEN};
+use std::fmt;
+use std::io;
+
+/// `ReportKind` is the direction of a report.
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum ReportKind {
+    Input,
+    Output,
+    Feature,
+}
+
+impl fmt::Display for ReportKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ReportKind::Input => write!(f, "input"),
+            ReportKind::Output => write!(f, "output"),
+            ReportKind::Feature => write!(f, "feature"),
+        }
+    }
+}
+
+/// `Report` is one report declared by the device.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Report {
+    pub kind: ReportKind,
+    /// The report ID, or `0` if the device does not number its reports.
+    pub id: u8,
+    /// Length in bytes, without the report ID.
+    pub len: usize,
+}
+
+/// `ReportDescriptor` lists the reports declared in a HID report descriptor.
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct ReportDescriptor {
+    /// Reports ordered by kind and ID.
+    pub reports: Vec<Report>,
+}
+
+/// The global items that determine report sizes.
+#[derive(Debug, Copy, Clone, Default)]
+struct Globals {
+    report_size: u32,
+    report_count: u32,
+    report_id: u8,
+}
+
+impl ReportDescriptor {
+    /// Parses a raw HID report descriptor.
+    pub fn parse(data: &[u8]) -> io::Result<ReportDescriptor> {
+        let invalid = |message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("HID report descriptor: {}", message),
+            )
+        };
+
+        // Bits per (kind, report ID), in declaration order
+        let mut bits: Vec<(ReportKind, u8, u32)> = Vec::new();
+        let mut globals = Globals::default();
+        let mut stack = Vec::new();
+
+        let mut i = 0;
+        while i < data.len() {
+            let prefix = data[i];
+            if prefix == 0xfe {
+                // Long item: size, tag and data, none of which we need
+                let size = *data
+                    .get(i + 1)
+                    .ok_or_else(|| invalid("truncated long item"))?
+                    as usize;
+                i += 3 + size;
+                continue;
+            }
+
+            let size = match prefix & 0x03 {
+                3 => 4,
+                n => n as usize,
+            };
+            let bytes = data
+                .get(i + 1..i + 1 + size)
+                .ok_or_else(|| invalid("truncated item"))?;
+            let value = bytes.iter().rev().fold(0u32, |v, b| v << 8 | *b as u32);
+            i += 1 + size;
+
+            match (prefix >> 2) & 0x03 {
+                // Main items
+                0 => {
+                    let kind = match prefix >> 4 {
+                        0x8 => ReportKind::Input,
+                        0x9 => ReportKind::Output,
+                        0xb => ReportKind::Feature,
+                        _ => continue,
+                    };
+                    let added = globals.report_size.saturating_mul(globals.report_count);
+                    match bits
+                        .iter_mut()
+                        .find(|(k, id, _)| *k == kind && *id == globals.report_id)
+                    {
+                        Some((_, _, total)) => *total = total.saturating_add(added),
+                        None => bits.push((kind, globals.report_id, added)),
+                    }
+                }
+                // Global items
+                1 => match prefix >> 4 {
+                    0x7 => globals.report_size = value,
+                    0x8 => {
+                        globals.report_id =
+                            u8::try_from(value).map_err(|_| invalid("report ID out of range"))?;
+                        if globals.report_id == 0 {
+                            return Err(invalid("report ID 0 is reserved"));
+                        }
+                    }
+                    0x9 => globals.report_count = value,
+                    0xa => stack.push(globals),
+                    0xb => globals = stack.pop().ok_or_else(|| invalid("pop without push"))?,
+                    _ => (),
+                },
+                _ => (),
+            }
+        }
+
+        let mut reports: Vec<Report> = bits
+            .into_iter()
+            .map(|(kind, id, bits)| Report {
+                kind,
+                id,
+                len: bits.div_ceil(8) as usize,
+            })
+            .collect();
+        reports.sort_by_key(|r| (r.kind, r.id));
+
+        Ok(ReportDescriptor { reports })
+    }
+
+    /// Returns the report of `kind` with `id`, if declared.
+    pub fn report(&self, kind: ReportKind, id: u8) -> Option<&Report> {
+        self.reports.iter().find(|r| r.kind == kind && r.id == id)
+    }
+
+    /// Returns the declared feature reports.
+    pub fn feature_reports(&self) -> impl Iterator<Item = &Report> {
+        self.reports
+            .iter()
+            .filter(|r| r.kind == ReportKind::Feature)
+    }
+
+    /// Checks that the device declares the unnumbered 6-byte input report and 2-byte output report
+    /// the controller exchanges. Returns an `ErrorKind::InvalidData` error describing the mismatch.
+    pub fn check(&self) -> io::Result<()> {
+        for (kind, len) in [
+            (ReportKind::Input, REPORT_LEN),
+            (ReportKind::Output, OUTPUT_REPORT_LEN),
+        ] {
+            let declared = match self.report(kind, 0) {
+                Some(report) => format!("a {}-byte {} report", report.len, kind),
+                None => match self.reports.iter().find(|r| r.kind == kind) {
+                    Some(report) => format!("{} report ID {}", kind, report.id),
+                    None => format!("no {} report", kind),
+                },
+            };
+            if self.report(kind, 0).map(|r| r.len) != Some(len) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "BS5 controller declares {}, expected an unnumbered {}-byte {} report",
+                        declared, len, kind
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A descriptor declaring exactly the reports a BeoSound 5 panel is used with, for the virtual
+/// panel. Written by hand, not read from a real panel.
+pub(crate) const PANEL_DESCRIPTOR: &[u8] = &[
+    0x06, 0x00, 0xff, // Usage Page (Vendor Defined 0xFF00)
+    0x09, 0x01, // Usage (0x01)
+    0xa1, 0x01, // Collection (Application)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xff, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x06, //   Report Count (6)
+    0x09, 0x01, //   Usage (0x01)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x95, 0x02, //   Report Count (2)
+    0x09, 0x01, //   Usage (0x01)
+    0x91, 0x02, //   Output (Data, Variable, Absolute)
+    0xc0, // End Collection
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_panel_descriptor() {
+        let descriptor = ReportDescriptor::parse(PANEL_DESCRIPTOR).unwrap();
+
+        assert_eq!(
+            descriptor.reports,
+            vec![
+                Report {
+                    kind: ReportKind::Input,
+                    id: 0,
+                    len: 6
+                },
+                Report {
+                    kind: ReportKind::Output,
+                    id: 0,
+                    len: 2
+                },
+            ]
+        );
+        assert!(descriptor.check().is_ok());
+    }
+
+    #[test]
+    fn parses_numbered_and_feature_reports() {
+        let data = [
+            0x85, 0x01, // Report ID (1)
+            0x75, 0x01, // Report Size (1)
+            0x95, 0x0c, // Report Count (12)
+            0x81, 0x02, // Input
+            0xa4, // Push
+            0x75, 0x08, // Report Size (8)
+            0x95, 0x04, // Report Count (4)
+            0xb1, 0x02, // Feature
+            0xb4, // Pop
+            0x81, 0x01, // Input, another 12 bits
+            0x85, 0x02, // Report ID (2)
+            0xb1, 0x02, // Feature, 12 bits
+        ];
+        let descriptor = ReportDescriptor::parse(&data).unwrap();
+
+        assert_eq!(descriptor.report(ReportKind::Input, 1).unwrap().len, 3);
+        let features: Vec<_> = descriptor
+            .feature_reports()
+            .map(|r| (r.id, r.len))
+            .collect();
+        assert_eq!(features, vec![(1, 4), (2, 2)]);
+
+        let err = descriptor.check().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("input report ID 1"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_truncated_descriptors() {
+        assert!(ReportDescriptor::parse(&[0x75]).is_err());
+        assert!(ReportDescriptor::parse(&[0xb4]).is_err());
+    }
+}
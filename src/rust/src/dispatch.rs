@@ -0,0 +1,306 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Delivery of events to registered callbacks.
+//!
+//! Every callback is called in isolation: an `Err` or a panic from one callback is reported as a
+//! [`DispatchError`] and the remaining callbacks still receive the event. A [`FailurePolicy`] can
+//! remove callbacks that keep failing.
+
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A callback registered with the controller, receiving events of type `A`.
+pub type Callback<A> = Arc<Mutex<dyn Fn(A) -> Result<(), Box<dyn Error + Send>> + Send>>;
+
+/// Callback receiving the errors of other callbacks.
+pub type ErrorCallback = Arc<Mutex<dyn Fn(DispatchError) + Send>>;
+
+/// `CallbackKind` tells which kind of callback a [`DispatchError`] is about.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum CallbackKind {
+    Device,
+    Wheel,
+    Button,
+    Event,
+}
+
+impl fmt::Display for CallbackKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CallbackKind::Device => write!(f, "Device"),
+            CallbackKind::Wheel => write!(f, "Wheel"),
+            CallbackKind::Button => write!(f, "Button"),
+            CallbackKind::Event => write!(f, "Event"),
+        }
+    }
+}
+
+/// `Failure` describes what went wrong in a callback.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Failure {
+    /// The callback returned an `Err`.
+    Failed(String),
+    /// The callback panicked.
+    Panicked(String),
+    /// The callback failed too often in a row and was removed, see [`FailurePolicy`].
+    Removed,
+}
+
+/// `DispatchError` reports a failing callback.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DispatchError {
+    /// Which kind of callback failed.
+    pub kind: CallbackKind,
+    /// The registration order of the callback among callbacks of the same kind, starting at `0`.
+    pub callback: usize,
+    pub failure: Failure,
+}
+
+impl fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.failure {
+            Failure::Failed(err) => write!(
+                f,
+                "{} callback #{} failed: {}",
+                self.kind, self.callback, err
+            ),
+            Failure::Panicked(msg) => write!(
+                f,
+                "{} callback #{} panicked: {}",
+                self.kind, self.callback, msg
+            ),
+            Failure::Removed => write!(
+                f,
+                "{} callback #{} removed after repeated failures",
+                self.kind, self.callback
+            ),
+        }
+    }
+}
+
+impl Error for DispatchError {}
+
+/// `FailurePolicy` decides what happens to callbacks that keep failing.
+#[derive(Debug, Copy, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct FailurePolicy {
+    /// Remove a callback after this many failures in a row. `None` keeps failing callbacks forever.
+    pub max_consecutive_failures: Option<u32>,
+}
+
+struct Subscriber<A> {
+    id: usize,
+    callback: Callback<A>,
+    failures: u32,
+}
+
+/// `Subscribers` is a list of callbacks of one kind.
+pub(crate) struct Subscribers<A> {
+    kind: CallbackKind,
+    next_id: usize,
+    entries: Vec<Subscriber<A>>,
+}
+
+impl<A: Copy> Subscribers<A> {
+    pub(crate) fn new(kind: CallbackKind) -> Subscribers<A> {
+        Subscribers {
+            kind,
+            next_id: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, callback: Callback<A>) {
+        self.entries.push(Subscriber {
+            id: self.next_id,
+            callback,
+            failures: 0,
+        });
+        self.next_id += 1;
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Calls every callback in `subscribers` with `arg`, isolating failures.
+///
+/// The list is not locked while callbacks run, so callbacks may register further callbacks.
+/// Failures are handed to `errors`, and callbacks are removed as `policy` dictates.
+pub(crate) fn dispatch<A: Copy>(
+    subscribers: &Mutex<Subscribers<A>>,
+    arg: A,
+    policy: FailurePolicy,
+    errors: &Mutex<Vec<ErrorCallback>>,
+) {
+    let (kind, snapshot): (CallbackKind, Vec<(usize, Callback<A>)>) = {
+        let subscribers = subscribers.lock().unwrap_or_else(PoisonError::into_inner);
+        let snapshot = subscribers
+            .entries
+            .iter()
+            .map(|s| (s.id, s.callback.clone()))
+            .collect();
+        (subscribers.kind, snapshot)
+    };
+
+    let mut outcomes = Vec::with_capacity(snapshot.len());
+    for (id, callback) in snapshot {
+        // A callback that panicked elsewhere poisons its mutex, it is still safe to call.
+        let callback = callback.lock().unwrap_or_else(PoisonError::into_inner);
+        let outcome = match panic::catch_unwind(AssertUnwindSafe(|| callback(arg))) {
+            Ok(Ok(())) => None,
+            Ok(Err(err)) => Some(Failure::Failed(err.to_string())),
+            Err(panic) => Some(Failure::Panicked(panic_message(panic.as_ref()))),
+        };
+        outcomes.push((id, outcome));
+    }
+
+    let mut reports = Vec::new();
+    {
+        let mut subscribers = subscribers.lock().unwrap_or_else(PoisonError::into_inner);
+        for (id, outcome) in outcomes {
+            let Some(subscriber) = subscribers.entries.iter_mut().find(|s| s.id == id) else {
+                continue;
+            };
+            match outcome {
+                None => subscriber.failures = 0,
+                Some(failure) => {
+                    subscriber.failures += 1;
+                    reports.push(DispatchError {
+                        kind,
+                        callback: id,
+                        failure,
+                    });
+                    if policy
+                        .max_consecutive_failures
+                        .is_some_and(|max| subscriber.failures >= max)
+                    {
+                        subscribers.entries.retain(|s| s.id != id);
+                        reports.push(DispatchError {
+                            kind,
+                            callback: id,
+                            failure: Failure::Removed,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    for report in reports {
+        report_error(errors, report);
+    }
+}
+
+/// Hands `error` to every error callback, or prints it when there are none.
+pub(crate) fn report_error(errors: &Mutex<Vec<ErrorCallback>>, error: DispatchError) {
+    let callbacks = errors
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    if callbacks.is_empty() {
+        eprintln!("{}", error);
+        return;
+    }
+
+    for callback in callbacks {
+        let callback = callback.lock().unwrap_or_else(PoisonError::into_inner);
+        // An error callback that panics has nobody left to report to
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| callback(error.clone())));
+    }
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    if let Some(msg) = panic.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = panic.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn counting(counter: Arc<AtomicUsize>) -> Callback<u8> {
+        Arc::new(Mutex::new(
+            move |_: u8| -> Result<(), Box<dyn Error + Send>> {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            },
+        ))
+    }
+
+    fn collecting(errors: Arc<Mutex<Vec<DispatchError>>>) -> Mutex<Vec<ErrorCallback>> {
+        let sink: ErrorCallback = Arc::new(Mutex::new(move |err: DispatchError| {
+            errors.lock().unwrap().push(err)
+        }));
+        Mutex::new(vec![sink])
+    }
+
+    #[test]
+    fn failing_callbacks_do_not_stop_delivery() {
+        let delivered = Arc::new(AtomicUsize::new(0));
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let subscribers = Mutex::new(Subscribers::new(CallbackKind::Wheel));
+        {
+            let mut s = subscribers.lock().unwrap();
+            s.push(Arc::new(Mutex::new(
+                |_: u8| -> Result<(), Box<dyn Error + Send>> {
+                    Err(Box::new(std::io::Error::other("nope")))
+                },
+            )));
+            s.push(Arc::new(Mutex::new(
+                |_: u8| -> Result<(), Box<dyn Error + Send>> { panic!("boom") },
+            )));
+            s.push(counting(delivered.clone()));
+        }
+
+        dispatch(
+            &subscribers,
+            1,
+            FailurePolicy::default(),
+            &collecting(reported.clone()),
+        );
+
+        assert_eq!(delivered.load(Ordering::SeqCst), 1);
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported.len(), 2);
+        assert_eq!(reported[0].failure, Failure::Failed("nope".to_string()));
+        assert_eq!(reported[1].failure, Failure::Panicked("boom".to_string()));
+        assert_eq!(subscribers.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn policy_removes_repeatedly_failing_callbacks() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let errors = collecting(reported.clone());
+        let subscribers = Mutex::new(Subscribers::new(CallbackKind::Button));
+        subscribers.lock().unwrap().push(Arc::new(Mutex::new(
+            |_: u8| -> Result<(), Box<dyn Error + Send>> {
+                Err(Box::new(std::io::Error::other("nope")))
+            },
+        )));
+        let policy = FailurePolicy {
+            max_consecutive_failures: Some(2),
+        };
+
+        dispatch(&subscribers, 1, policy, &errors);
+        assert_eq!(subscribers.lock().unwrap().len(), 1);
+        dispatch(&subscribers, 1, policy, &errors);
+        assert_eq!(subscribers.lock().unwrap().len(), 0);
+        assert_eq!(
+            reported.lock().unwrap().last().unwrap().failure,
+            Failure::Removed
+        );
+    }
+}
@@ -0,0 +1,286 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Protocol exploration helpers for mapping the undocumented parts of the panel protocol.
+//!
+//! A [`ProtocolExplorer`] watches every input report and keeps statistics per bit: how often it
+//! changed, how often it was set, and which known [`Event`]s were decoded from the same report.
+//! Bits the decoder does not understand are flagged as unknown, so they stand out in the
+//! [`ExplorationReport`].
+//!
+//! [`Beolyd5Controller::sweep_output`](crate::Beolyd5Controller::sweep_output) complements this
+//! by walking through output report values and recording which input bits moved while each
+//! value was applied.
+
+use crate::decoder::REPORT_LEN;
+use crate::types::Event;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Bits of each input report byte that the decoder understands.
+pub const KNOWN_BITS: [u8; REPORT_LEN] = [0xff, 0xff, 0xff, 0xf0, 0x00, 0x00];
+
+/// Label used for bit changes that happened without any decoded event.
+pub const NO_EVENT: &str = "none";
+
+/// `BitStats` holds the statistics for a single bit of the input report.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BitStats {
+    /// Byte offset in the input report.
+    pub byte: usize,
+    /// Bit number within the byte, `0` being the least significant bit.
+    pub bit: u8,
+    /// Whether the decoder knows what this bit means.
+    pub known: bool,
+    /// Number of reports in which the bit differed from the previous report.
+    pub changes: u64,
+    /// Number of reports in which the bit was set.
+    pub set: u64,
+    /// Known events decoded from the reports in which this bit changed, by event label.
+    pub co_events: BTreeMap<String, u64>,
+}
+
+/// `ExplorationReport` is a snapshot of everything a [`ProtocolExplorer`] has seen.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ExplorationReport {
+    /// Number of input reports observed.
+    pub reports: u64,
+    /// Statistics for every bit that changed at least once.
+    pub bits: Vec<BitStats>,
+}
+
+impl ExplorationReport {
+    /// Returns the statistics for the bits the decoder does not understand.
+    pub fn unknown_bits(&self) -> impl Iterator<Item = &BitStats> {
+        self.bits.iter().filter(|b| !b.known)
+    }
+}
+
+/// `ProtocolExplorer` records which input report bits change, how often, and alongside which known events.
+#[derive(Debug, Clone)]
+pub struct ProtocolExplorer {
+    last_report: Option<[u8; REPORT_LEN]>,
+    reports: u64,
+    bits: Vec<BitStats>,
+}
+
+impl Default for ProtocolExplorer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProtocolExplorer {
+    /// Creates a new explorer that has not seen any reports yet.
+    pub fn new() -> ProtocolExplorer {
+        let bits = (0..REPORT_LEN)
+            .flat_map(|byte| {
+                (0..8u8).map(move |bit| BitStats {
+                    byte,
+                    bit,
+                    known: KNOWN_BITS[byte] & (1 << bit) != 0,
+                    ..BitStats::default()
+                })
+            })
+            .collect();
+
+        ProtocolExplorer {
+            last_report: None,
+            reports: 0,
+            bits,
+        }
+    }
+
+    /// Records a report together with the events the decoder produced for it.
+    /// Returns the `(byte, mask)` pairs of unknown bits that changed compared to the previous report.
+    pub fn observe(&mut self, report: [u8; REPORT_LEN], events: &[Event]) -> Vec<(usize, u8)> {
+        let previous = self.last_report.unwrap_or(report);
+        let labels: Vec<String> = if events.is_empty() {
+            vec![NO_EVENT.to_string()]
+        } else {
+            events.iter().map(event_label).collect()
+        };
+
+        let mut unknown_changes = Vec::new();
+        for (byte, (&now, &before)) in report.iter().zip(previous.iter()).enumerate() {
+            let changed = now ^ before;
+            if changed & !KNOWN_BITS[byte] != 0 {
+                unknown_changes.push((byte, changed & !KNOWN_BITS[byte]));
+            }
+
+            for bit in 0..8u8 {
+                let mask = 1 << bit;
+                let stats = &mut self.bits[byte * 8 + bit as usize];
+                if now & mask != 0 {
+                    stats.set += 1;
+                }
+                if changed & mask != 0 {
+                    stats.changes += 1;
+                    for label in &labels {
+                        *stats.co_events.entry(label.clone()).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+
+        self.reports += 1;
+        self.last_report = Some(report);
+        unknown_changes
+    }
+
+    /// Returns a snapshot of the statistics gathered so far, leaving out bits that never changed.
+    pub fn report(&self) -> ExplorationReport {
+        ExplorationReport {
+            reports: self.reports,
+            bits: self
+                .bits
+                .iter()
+                .filter(|b| b.changes > 0)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Forgets everything seen so far.
+    pub fn reset(&mut self) {
+        *self = ProtocolExplorer::new();
+    }
+}
+
+/// `SweepConfig` describes a controlled walk through output report values.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SweepConfig {
+    /// Which byte of the 2-byte output report to sweep.
+    pub byte: usize,
+    /// First value to send (inclusive).
+    pub from: u8,
+    /// Last value to send (inclusive).
+    pub to: u8,
+    /// The output report the swept byte is merged into, and which is restored afterwards.
+    pub baseline: [u8; 2],
+    /// How long each value is held before moving on.
+    pub dwell: Duration,
+}
+
+impl Default for SweepConfig {
+    fn default() -> Self {
+        SweepConfig {
+            byte: 1,
+            from: 0x00,
+            to: 0xff,
+            baseline: [0x40, 0x00],
+            dwell: Duration::from_millis(250),
+        }
+    }
+}
+
+/// `SweepStep` is the outcome of holding a single output report value during a sweep.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SweepStep {
+    /// The output report that was sent.
+    pub output: [u8; 2],
+    /// Number of input reports received while the value was held.
+    pub reports: u64,
+    /// Input bits that changed while the value was held, as `(byte, mask)` pairs.
+    pub changed_bits: Vec<(usize, u8)>,
+}
+
+pub(crate) fn event_label(event: &Event) -> String {
+    match event {
+        Event::WheelMoved(wheel, _) => format!("WheelMoved({})", wheel),
+        Event::ButtonPressed(button) => format!("ButtonPressed({})", button),
+        Event::ButtonReleased(button) => format!("ButtonReleased({})", button),
+    }
+}
+
+pub(crate) fn changed_bits(
+    before: &ExplorationReport,
+    after: &ExplorationReport,
+) -> Vec<(usize, u8)> {
+    let mut masks = [0u8; REPORT_LEN];
+    for stats in &after.bits {
+        let previous = before
+            .bits
+            .iter()
+            .find(|b| b.byte == stats.byte && b.bit == stats.bit)
+            .map(|b| b.changes)
+            .unwrap_or(0);
+        if stats.changes > previous {
+            masks[stats.byte] |= 1 << stats.bit;
+        }
+    }
+
+    masks
+        .iter()
+        .enumerate()
+        .filter(|(_, &mask)| mask != 0)
+        .map(|(byte, &mask)| (byte, mask))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::BUTTON_BYTE;
+    use crate::types::{Button, Wheel};
+
+    #[test]
+    fn unknown_bits_are_flagged() {
+        let mut explorer = ProtocolExplorer::new();
+        explorer.observe([0, 0, 0, 0, 0, 0], &[]);
+        let unknown = explorer.observe([0, 0, 0, 0x01, 0x80, 0], &[]);
+
+        assert_eq!(unknown, vec![(BUTTON_BYTE, 0x01), (4, 0x80)]);
+        let report = explorer.report();
+        assert_eq!(report.reports, 2);
+        assert_eq!(report.unknown_bits().count(), 2);
+        assert_eq!(
+            report
+                .unknown_bits()
+                .next()
+                .unwrap()
+                .co_events
+                .get(NO_EVENT),
+            Some(&1)
+        );
+    }
+
+    #[test]
+    fn changes_are_attributed_to_known_events() {
+        let mut explorer = ProtocolExplorer::new();
+        explorer.observe([0, 0, 0, 0, 0, 0], &[]);
+        explorer.observe(
+            [0, 0, 0, 0x40, 0x02, 0],
+            &[Event::ButtonPressed(Button::Go)],
+        );
+        explorer.observe(
+            [0x01, 0, 0, 0x40, 0x02, 0],
+            &[Event::WheelMoved(Wheel::Front, 1)],
+        );
+
+        let report = explorer.report();
+        let byte4 = report
+            .bits
+            .iter()
+            .find(|b| b.byte == 4 && b.bit == 1)
+            .unwrap();
+        assert_eq!(byte4.changes, 1);
+        assert_eq!(byte4.set, 2);
+        assert_eq!(byte4.co_events.get("ButtonPressed(Go)"), Some(&1));
+    }
+
+    #[test]
+    fn changed_bits_between_snapshots() {
+        let mut explorer = ProtocolExplorer::new();
+        explorer.observe([0, 0, 0, 0, 0, 0], &[]);
+        let before = explorer.report();
+        explorer.observe([0, 0, 0, 0, 0x03, 0x10], &[]);
+
+        assert_eq!(
+            changed_bits(&before, &explorer.report()),
+            vec![(4, 0x03), (5, 0x10)]
+        );
+    }
+}
@@ -0,0 +1,357 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Composable transforms between decoding and the callbacks.
+//!
+//! Every decoded [`Event`] runs through an ordered chain of [`EventFilter`] stages, which may
+//! change or drop it, before it is dispatched. The built-in stages cover the usual needs:
+//!
+//! - [`Invert`] swaps the direction of a wheel,
+//! - [`Scale`] speeds a wheel up or slows it down,
+//! - [`Deadband`] ignores small movements, e.g. jitter of the angular wheel,
+//! - [`Debounce`] ignores a button bouncing right after it was released,
+//! - [`Remap`] turns one button or wheel into another.
+//!
+//! Any `FnMut(Event, Instant) -> Option<Event>` closure is a stage too. Stages run after the
+//! per-panel [`settings`](crate::settings); device callbacks always receive the raw reports.
+
+use crate::accumulator::relative_movement;
+use crate::decoder::ANGULAR_MAX;
+use crate::settings::{self, WheelSettings};
+use crate::types::{Button, Event, Wheel};
+use std::time::{Duration, Instant};
+
+/// `EventFilter` is one stage of the chain.
+pub trait EventFilter: Send {
+    /// Returns the event to pass on to the next stage, or `None` to drop it.
+    fn filter(&mut self, event: Event, now: Instant) -> Option<Event>;
+}
+
+impl<F> EventFilter for F
+where
+    F: FnMut(Event, Instant) -> Option<Event> + Send,
+{
+    fn filter(&mut self, event: Event, now: Instant) -> Option<Event> {
+        self(event, now)
+    }
+}
+
+/// `FilterChain` runs events through its stages in the order they were added.
+#[derive(Default)]
+pub struct FilterChain {
+    stages: Vec<Box<dyn EventFilter>>,
+}
+
+impl FilterChain {
+    pub fn new() -> FilterChain {
+        FilterChain::default()
+    }
+
+    /// Adds a stage at the end of the chain.
+    pub fn push(&mut self, stage: impl EventFilter + 'static) {
+        self.stages.push(Box::new(stage));
+    }
+
+    /// Removes all stages.
+    pub fn clear(&mut self) {
+        self.stages.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Runs `event` through every stage, stopping at the first one that drops it.
+    pub fn apply(&mut self, event: Event, now: Instant) -> Option<Event> {
+        self.stages
+            .iter_mut()
+            .try_fold(event, |event, stage| stage.filter(event, now))
+    }
+}
+
+/// `Invert` swaps clockwise and counter-clockwise for a wheel. The angular wheel is mirrored, so
+/// `0` becomes `ANGULAR_MAX`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Invert {
+    pub wheel: Wheel,
+}
+
+impl Invert {
+    pub fn new(wheel: Wheel) -> Invert {
+        Invert { wheel }
+    }
+}
+
+impl EventFilter for Invert {
+    fn filter(&mut self, event: Event, _now: Instant) -> Option<Event> {
+        match event {
+            Event::WheelMoved(wheel, pos) if wheel == self.wheel => match wheel {
+                Wheel::Angular => Some(Event::WheelMoved(wheel, ANGULAR_MAX.saturating_sub(pos))),
+                _ => Some(Event::WheelMoved(
+                    wheel,
+                    relative_movement(pos).wrapping_neg() as u8,
+                )),
+            },
+            _ => Some(event),
+        }
+    }
+}
+
+/// `Scale` multiplies the movement of the front or back wheel by `factor`. Fractions of a step
+/// are carried over, so a factor of `0.5` reports every second step.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Scale {
+    pub wheel: Wheel,
+    pub factor: f64,
+    remainder: f64,
+}
+
+impl Scale {
+    pub fn new(wheel: Wheel, factor: f64) -> Scale {
+        Scale {
+            wheel,
+            factor,
+            remainder: 0.0,
+        }
+    }
+}
+
+impl EventFilter for Scale {
+    fn filter(&mut self, event: Event, _now: Instant) -> Option<Event> {
+        match event {
+            Event::WheelMoved(wheel, pos) if wheel == self.wheel && wheel != Wheel::Angular => {
+                let settings = WheelSettings {
+                    sensitivity: self.factor,
+                    ..WheelSettings::default()
+                };
+                settings::scale(&settings, &mut self.remainder, pos)
+                    .map(|pos| Event::WheelMoved(wheel, pos))
+            }
+            _ => Some(event),
+        }
+    }
+}
+
+/// `Deadband` drops wheel movements smaller than `threshold` steps. For the angular wheel, a
+/// position is only passed on once it is `threshold` away from the last one passed on, which
+/// hides jitter.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Deadband {
+    pub wheel: Wheel,
+    pub threshold: u8,
+    last_angular: Option<u8>,
+}
+
+impl Deadband {
+    pub fn new(wheel: Wheel, threshold: u8) -> Deadband {
+        Deadband {
+            wheel,
+            threshold,
+            last_angular: None,
+        }
+    }
+}
+
+impl EventFilter for Deadband {
+    fn filter(&mut self, event: Event, _now: Instant) -> Option<Event> {
+        match event {
+            Event::WheelMoved(Wheel::Angular, pos) if self.wheel == Wheel::Angular => {
+                if let Some(last) = self.last_angular {
+                    if last.abs_diff(pos) < self.threshold {
+                        return None;
+                    }
+                }
+                self.last_angular = Some(pos);
+                Some(event)
+            }
+            Event::WheelMoved(wheel, pos) if wheel == self.wheel => {
+                (relative_movement(pos).unsigned_abs() >= self.threshold).then_some(event)
+            }
+            _ => Some(event),
+        }
+    }
+}
+
+/// `Debounce` drops a button press that comes within `interval` of the release of the same
+/// button, together with its release.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Debounce {
+    pub interval: Duration,
+    last_release: Option<(Button, Instant)>,
+    suppressed: Option<Button>,
+}
+
+impl Debounce {
+    pub fn new(interval: Duration) -> Debounce {
+        Debounce {
+            interval,
+            last_release: None,
+            suppressed: None,
+        }
+    }
+}
+
+impl EventFilter for Debounce {
+    fn filter(&mut self, event: Event, now: Instant) -> Option<Event> {
+        match event {
+            Event::ButtonPressed(button) => match self.last_release {
+                Some((released, at))
+                    if released == button && now.saturating_duration_since(at) < self.interval =>
+                {
+                    self.suppressed = Some(button);
+                    None
+                }
+                _ => Some(event),
+            },
+            Event::ButtonReleased(button) if self.suppressed == Some(button) => {
+                self.suppressed = None;
+                None
+            }
+            Event::ButtonReleased(button) => {
+                self.last_release = Some((button, now));
+                Some(event)
+            }
+            _ => Some(event),
+        }
+    }
+}
+
+/// `Remap` replaces buttons and wheels. Pairs are `(from, to)`; anything not listed passes
+/// unchanged. The position of a remapped wheel is passed on as is.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Remap {
+    pub buttons: Vec<(Button, Button)>,
+    pub wheels: Vec<(Wheel, Wheel)>,
+}
+
+impl Remap {
+    pub fn new(buttons: Vec<(Button, Button)>, wheels: Vec<(Wheel, Wheel)>) -> Remap {
+        Remap { buttons, wheels }
+    }
+
+    fn button(&self, button: Button) -> Button {
+        self.buttons
+            .iter()
+            .find(|(from, _)| *from == button)
+            .map_or(button, |(_, to)| *to)
+    }
+}
+
+impl EventFilter for Remap {
+    fn filter(&mut self, event: Event, _now: Instant) -> Option<Event> {
+        Some(match event {
+            Event::WheelMoved(wheel, pos) => {
+                let wheel = self
+                    .wheels
+                    .iter()
+                    .find(|(from, _)| *from == wheel)
+                    .map_or(wheel, |(_, to)| *to);
+                Event::WheelMoved(wheel, pos)
+            }
+            Event::ButtonPressed(button) => Event::ButtonPressed(self.button(button)),
+            Event::ButtonReleased(button) => Event::ButtonReleased(self.button(button)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(chain: &mut FilterChain, events: &[(u64, Event)]) -> Vec<Event> {
+        let start = Instant::now();
+        events
+            .iter()
+            .filter_map(|(millis, event)| {
+                chain.apply(*event, start + Duration::from_millis(*millis))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stages_run_in_order() {
+        let mut chain = FilterChain::new();
+        chain.push(Remap::new(vec![], vec![(Wheel::Back, Wheel::Front)]));
+        chain.push(Invert::new(Wheel::Front));
+        chain.push(Scale::new(Wheel::Front, 2.0));
+
+        let events = run(
+            &mut chain,
+            &[
+                (0, Event::WheelMoved(Wheel::Back, 0x01)),
+                (0, Event::WheelMoved(Wheel::Angular, 10)),
+            ],
+        );
+        assert_eq!(
+            events,
+            vec![
+                Event::WheelMoved(Wheel::Front, 0xfe),
+                Event::WheelMoved(Wheel::Angular, 10)
+            ]
+        );
+
+        chain.push(|event: Event, _now: Instant| match event {
+            Event::WheelMoved(Wheel::Angular, _) => None,
+            _ => Some(event),
+        });
+        assert!(run(&mut chain, &[(0, Event::WheelMoved(Wheel::Angular, 10))]).is_empty());
+    }
+
+    #[test]
+    fn deadband_hides_angular_jitter() {
+        let mut chain = FilterChain::new();
+        chain.push(Deadband::new(Wheel::Angular, 3));
+        chain.push(Deadband::new(Wheel::Front, 2));
+
+        let events = run(
+            &mut chain,
+            &[
+                (0, Event::WheelMoved(Wheel::Angular, 40)),
+                (0, Event::WheelMoved(Wheel::Angular, 41)),
+                (0, Event::WheelMoved(Wheel::Angular, 39)),
+                (0, Event::WheelMoved(Wheel::Angular, 43)),
+                (0, Event::WheelMoved(Wheel::Front, 0x01)),
+                (0, Event::WheelMoved(Wheel::Front, 0xfe)),
+            ],
+        );
+        assert_eq!(
+            events,
+            vec![
+                Event::WheelMoved(Wheel::Angular, 40),
+                Event::WheelMoved(Wheel::Angular, 43),
+                Event::WheelMoved(Wheel::Front, 0xfe),
+            ]
+        );
+    }
+
+    #[test]
+    fn debounce_drops_bounces_in_pairs() {
+        let mut chain = FilterChain::new();
+        chain.push(Debounce::new(Duration::from_millis(30)));
+
+        let events = run(
+            &mut chain,
+            &[
+                (0, Event::ButtonPressed(Button::Go)),
+                (100, Event::ButtonReleased(Button::Go)),
+                (110, Event::ButtonPressed(Button::Go)),
+                (115, Event::ButtonReleased(Button::Go)),
+                (200, Event::ButtonPressed(Button::Go)),
+            ],
+        );
+        assert_eq!(
+            events,
+            vec![
+                Event::ButtonPressed(Button::Go),
+                Event::ButtonReleased(Button::Go),
+                Event::ButtonPressed(Button::Go),
+            ]
+        );
+    }
+}
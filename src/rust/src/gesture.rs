@@ -0,0 +1,309 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Gestures recognized from the wheel and button events.
+//!
+//! A [`GestureRecognizer`] is fed decoded [`Event`]s together with the time they arrived and
+//! reports [`Gesture`]s on top of them:
+//!
+//! - a *flick* is a fast, short spin, e.g. to jump a page or a letter,
+//! - a *hold-and-turn* is a wheel turned while a button is held, e.g. Go and the back wheel for
+//!   fine volume,
+//! - a *reverse scrub* is a quick turn one way and back.
+//!
+//! The events are still delivered as usual; gestures come in addition to them.
+
+use crate::accumulator::relative_movement;
+use crate::types::{Button, Event, Wheel};
+use std::time::{Duration, Instant};
+
+/// `Direction` is the way a wheel turned.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Direction {
+    Clockwise,
+    CounterClockwise,
+}
+
+/// `Gesture` is a higher level movement recognized by a [`GestureRecognizer`].
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Gesture {
+    /// A fast, short spin. Reported once per spin.
+    Flick { wheel: Wheel, direction: Direction },
+    /// A wheel moved while a button was held. `pos` is reported as in [`Event::WheelMoved`].
+    HoldAndTurn {
+        button: Button,
+        wheel: Wheel,
+        pos: u8,
+    },
+    /// The wheel turned one way and quickly back.
+    ReverseScrub { wheel: Wheel },
+}
+
+/// `GestureConfig` holds the thresholds of a [`GestureRecognizer`].
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GestureConfig {
+    /// A pause longer than this, in milliseconds, ends a spin.
+    pub idle_millis: u64,
+    /// Steps a spin must reach within `flick_millis` to be a flick.
+    pub flick_steps: u32,
+    pub flick_millis: u64,
+    /// Steps each way of a reverse scrub, which must be completed within `scrub_millis`.
+    pub scrub_steps: u32,
+    pub scrub_millis: u64,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        GestureConfig {
+            idle_millis: 150,
+            flick_steps: 6,
+            flick_millis: 150,
+            scrub_steps: 2,
+            scrub_millis: 400,
+        }
+    }
+}
+
+/// Movement of one wheel in one direction without a pause.
+#[derive(Debug, Copy, Clone)]
+struct Spin {
+    direction: Direction,
+    steps: u32,
+    started: Instant,
+    last: Instant,
+    flicked: bool,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct WheelTrack {
+    current: Option<Spin>,
+    /// The spin before `current`, if it went the other way right before it.
+    previous: Option<Spin>,
+}
+
+/// `GestureRecognizer` turns a stream of events into [`Gesture`]s.
+#[derive(Debug, Clone)]
+pub struct GestureRecognizer {
+    config: GestureConfig,
+    held: Button,
+    angular: Option<u8>,
+    front: WheelTrack,
+    back: WheelTrack,
+    angular_track: WheelTrack,
+}
+
+impl Default for GestureRecognizer {
+    fn default() -> Self {
+        Self::new(GestureConfig::default())
+    }
+}
+
+impl GestureRecognizer {
+    pub fn new(config: GestureConfig) -> GestureRecognizer {
+        GestureRecognizer {
+            config,
+            held: Button::None,
+            angular: None,
+            front: WheelTrack::default(),
+            back: WheelTrack::default(),
+            angular_track: WheelTrack::default(),
+        }
+    }
+
+    /// Returns the thresholds.
+    pub fn config(&self) -> &GestureConfig {
+        &self.config
+    }
+
+    /// Feeds an event that arrived at `now` and returns the gestures it completes.
+    pub fn handle(&mut self, event: Event, now: Instant) -> Vec<Gesture> {
+        match event {
+            Event::ButtonPressed(button) => {
+                self.held = button;
+                Vec::new()
+            }
+            Event::ButtonReleased(button) => {
+                if self.held == button {
+                    self.held = Button::None;
+                }
+                Vec::new()
+            }
+            Event::WheelMoved(wheel, pos) => self.wheel_moved(wheel, pos, now),
+        }
+    }
+
+    fn wheel_moved(&mut self, wheel: Wheel, pos: u8, now: Instant) -> Vec<Gesture> {
+        let steps = match wheel {
+            Wheel::Front | Wheel::Back => relative_movement(pos) as i32,
+            Wheel::Angular => {
+                let previous = self.angular.replace(pos);
+                previous.map(|p| pos as i32 - p as i32).unwrap_or(0)
+            }
+            Wheel::None => return Vec::new(),
+        };
+
+        if self.held != Button::None {
+            // Turning while holding a button is its own gesture, don't mistake it for a flick
+            *self.track(wheel) = WheelTrack::default();
+            return vec![Gesture::HoldAndTurn {
+                button: self.held,
+                wheel,
+                pos,
+            }];
+        }
+        if steps == 0 {
+            return Vec::new();
+        }
+
+        let config = self.config;
+        let track = self.track(wheel);
+        let direction = if steps > 0 {
+            Direction::Clockwise
+        } else {
+            Direction::CounterClockwise
+        };
+        let idle = Duration::from_millis(config.idle_millis);
+
+        match track.current {
+            Some(spin) if now.saturating_duration_since(spin.last) > idle => {
+                track.previous = None;
+                track.current = None;
+            }
+            Some(spin) if spin.direction != direction => {
+                track.previous = Some(spin);
+                track.current = None;
+            }
+            _ => (),
+        }
+        let spin = track.current.get_or_insert(Spin {
+            direction,
+            steps: 0,
+            started: now,
+            last: now,
+            flicked: false,
+        });
+        spin.steps += steps.unsigned_abs();
+        spin.last = now;
+
+        let mut gestures = Vec::new();
+        if !spin.flicked
+            && spin.steps >= config.flick_steps
+            && now.saturating_duration_since(spin.started)
+                <= Duration::from_millis(config.flick_millis)
+        {
+            spin.flicked = true;
+            gestures.push(Gesture::Flick { wheel, direction });
+        }
+
+        let spin = *spin;
+        if let Some(previous) = track.previous {
+            if previous.steps >= config.scrub_steps
+                && spin.steps >= config.scrub_steps
+                && now.saturating_duration_since(previous.started)
+                    <= Duration::from_millis(config.scrub_millis)
+            {
+                track.previous = None;
+                gestures.push(Gesture::ReverseScrub { wheel });
+            }
+        }
+
+        gestures
+    }
+
+    fn track(&mut self, wheel: Wheel) -> &mut WheelTrack {
+        match wheel {
+            Wheel::Back => &mut self.back,
+            Wheel::Angular => &mut self.angular_track,
+            _ => &mut self.front,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn fast_spin_is_a_flick() {
+        let start = Instant::now();
+        let mut recognizer = GestureRecognizer::default();
+
+        let mut gestures = Vec::new();
+        for i in 0..10 {
+            gestures.extend(
+                recognizer.handle(Event::WheelMoved(Wheel::Front, 0x01), at(start, i * 10)),
+            );
+        }
+        assert_eq!(
+            gestures,
+            vec![Gesture::Flick {
+                wheel: Wheel::Front,
+                direction: Direction::Clockwise
+            }]
+        );
+    }
+
+    #[test]
+    fn slow_spin_is_not_a_flick() {
+        let start = Instant::now();
+        let mut recognizer = GestureRecognizer::default();
+
+        for i in 0..10 {
+            assert!(recognizer
+                .handle(Event::WheelMoved(Wheel::Back, 0xff), at(start, i * 100))
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn turning_while_holding_a_button() {
+        let start = Instant::now();
+        let mut recognizer = GestureRecognizer::default();
+
+        recognizer.handle(Event::ButtonPressed(Button::Go), start);
+        assert_eq!(
+            recognizer.handle(Event::WheelMoved(Wheel::Back, 0x02), at(start, 10)),
+            vec![Gesture::HoldAndTurn {
+                button: Button::Go,
+                wheel: Wheel::Back,
+                pos: 0x02
+            }]
+        );
+        recognizer.handle(Event::ButtonReleased(Button::Go), at(start, 20));
+        assert!(recognizer
+            .handle(Event::WheelMoved(Wheel::Back, 0x02), at(start, 30))
+            .is_empty());
+    }
+
+    #[test]
+    fn back_and_forth_is_a_reverse_scrub() {
+        let start = Instant::now();
+        let mut recognizer = GestureRecognizer::default();
+
+        let moves = [(0, 0x01), (40, 0x01), (80, 0xff), (120, 0xff)];
+        let gestures: Vec<_> = moves
+            .iter()
+            .flat_map(|(t, pos)| {
+                recognizer.handle(Event::WheelMoved(Wheel::Front, *pos), at(start, *t))
+            })
+            .collect();
+        assert_eq!(
+            gestures,
+            vec![Gesture::ReverseScrub {
+                wheel: Wheel::Front
+            }]
+        );
+
+        // Too slow to count
+        let moves = [(1000, 0x01), (1140, 0x01), (1280, 0xff), (1420, 0xff)];
+        assert!(moves.iter().all(|(t, pos)| recognizer
+            .handle(Event::WheelMoved(Wheel::Front, *pos), at(start, *t))
+            .is_empty()));
+    }
+}
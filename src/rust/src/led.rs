@@ -0,0 +1,332 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Timed LED patterns.
+//!
+//! The panel LED only knows off, on and blink. An [`LedPattern`] describes anything else as a list
+//! of timed steps, and the [`LedScheduler`] decides which pattern drives the LED: an ambient one
+//! (e.g. a slow pulse while playing) that can be interrupted by notifications (e.g. a double blink
+//! on error). Notifications run by priority and the ambient pattern resumes when they are done.
+//!
+//! The controller runs a scheduler while it is open, see
+//! [`Beolyd5Controller::set_led_pattern`](crate::Beolyd5Controller::set_led_pattern) and
+//! [`Beolyd5Controller::notify_led`](crate::Beolyd5Controller::notify_led).
+
+use crate::types::Led;
+use std::time::{Duration, Instant};
+
+/// `LedStep` holds the LED in one state for a while.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LedStep {
+    pub led: Led,
+    /// How long the step lasts in milliseconds.
+    pub millis: u64,
+}
+
+impl LedStep {
+    pub fn new(led: Led, millis: u64) -> LedStep {
+        LedStep { led, millis }
+    }
+}
+
+/// `LedPattern` is a sequence of steps, played a number of times or forever.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LedPattern {
+    pub steps: Vec<LedStep>,
+    /// How many times the steps are played, or `None` to repeat forever.
+    /// A finished ambient pattern keeps the LED in the state of its last step.
+    pub repeat: Option<u32>,
+}
+
+impl LedPattern {
+    /// The LED held in one state.
+    pub fn solid(led: Led) -> LedPattern {
+        LedPattern {
+            steps: vec![LedStep::new(led, 0)],
+            repeat: None,
+        }
+    }
+
+    /// The LED on for `on_millis`, then off for `off_millis`, forever.
+    pub fn pulse(on_millis: u64, off_millis: u64) -> LedPattern {
+        LedPattern {
+            steps: vec![
+                LedStep::new(Led::On, on_millis),
+                LedStep::new(Led::Off, off_millis),
+            ],
+            repeat: None,
+        }
+    }
+
+    /// Two short blinks, played once.
+    pub fn double_blink() -> LedPattern {
+        LedPattern {
+            steps: vec![
+                LedStep::new(Led::On, 120),
+                LedStep::new(Led::Off, 120),
+                LedStep::new(Led::On, 120),
+                LedStep::new(Led::Off, 400),
+            ],
+            repeat: Some(1),
+        }
+    }
+
+    /// A slow rise and fall over `period_millis`, forever.
+    /// The LED has no brightness control, so this is approximated by a rising and falling duty cycle.
+    pub fn breathing(period_millis: u64) -> LedPattern {
+        const SLOTS: u64 = 8;
+        let slot = (period_millis / (2 * SLOTS)).max(1);
+        let duty = (1..=SLOTS).chain((1..=SLOTS).rev());
+
+        let steps = duty
+            .flat_map(|on| {
+                let on = slot * on / SLOTS;
+                [LedStep::new(Led::On, on), LedStep::new(Led::Off, slot - on)]
+            })
+            .filter(|step| step.millis > 0)
+            .collect();
+        LedPattern {
+            steps,
+            repeat: None,
+        }
+    }
+
+    /// Length of one run through the steps.
+    fn cycle(&self) -> Duration {
+        Duration::from_millis(self.steps.iter().map(|s| s.millis).sum())
+    }
+}
+
+/// A pattern being played since `started`.
+#[derive(Debug, Clone)]
+struct Playback {
+    pattern: LedPattern,
+    started: Instant,
+}
+
+impl Playback {
+    /// Returns the LED state at `now`, or `None` once a finite pattern has finished.
+    fn led_at(&self, now: Instant) -> Option<Led> {
+        let last = self.pattern.steps.last()?.led;
+        let cycle = self.pattern.cycle();
+        if cycle.is_zero() {
+            return Some(last);
+        }
+
+        let elapsed = now.saturating_duration_since(self.started);
+        let runs = (elapsed.as_millis() / cycle.as_millis()) as u64;
+        if self
+            .pattern
+            .repeat
+            .is_some_and(|repeat| runs >= repeat as u64)
+        {
+            return None;
+        }
+
+        let mut offset = (elapsed.as_millis() % cycle.as_millis()) as u64;
+        for step in &self.pattern.steps {
+            if offset < step.millis {
+                return Some(step.led);
+            }
+            offset -= step.millis;
+        }
+        Some(last)
+    }
+
+    fn last_led(&self) -> Led {
+        self.pattern.steps.last().map(|s| s.led).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Notification {
+    playback: Playback,
+    priority: u8,
+}
+
+/// `LedScheduler` decides the LED state from an ambient pattern and a queue of notifications.
+///
+/// It does not talk to the panel itself: call [`update`](LedScheduler::update) regularly and
+/// send the LED state it returns.
+#[derive(Debug, Clone, Default)]
+pub struct LedScheduler {
+    ambient: Option<Playback>,
+    active: Option<Notification>,
+    queue: Vec<Notification>,
+    led: Option<Led>,
+}
+
+impl LedScheduler {
+    /// Creates a scheduler that leaves the LED alone until a pattern is set.
+    pub fn new() -> LedScheduler {
+        LedScheduler::default()
+    }
+
+    /// Sets the pattern shown whenever no notification is playing.
+    pub fn set_ambient(&mut self, pattern: LedPattern, now: Instant) {
+        self.ambient = Some(Playback {
+            pattern,
+            started: now,
+        });
+    }
+
+    /// Queues a notification pattern. It interrupts a playing notification of lower priority,
+    /// which is played again from the start afterwards, and otherwise waits its turn.
+    /// A notification that repeats forever plays until [`clear_notifications`](LedScheduler::clear_notifications).
+    pub fn notify(&mut self, pattern: LedPattern, priority: u8, now: Instant) {
+        let notification = Notification {
+            playback: Playback {
+                pattern,
+                started: now,
+            },
+            priority,
+        };
+
+        match self.active.take() {
+            Some(active) if active.priority >= priority => {
+                self.active = Some(active);
+                self.enqueue(notification);
+            }
+            Some(active) => {
+                self.enqueue_front(active);
+                self.active = Some(notification);
+            }
+            None => self.active = Some(notification),
+        }
+    }
+
+    /// Drops the playing and all queued notifications, going back to the ambient pattern.
+    pub fn clear_notifications(&mut self) {
+        self.active = None;
+        self.queue.clear();
+    }
+
+    /// Returns the LED state last returned by [`update`](LedScheduler::update).
+    pub fn led(&self) -> Option<Led> {
+        self.led
+    }
+
+    /// Advances the patterns to `now`. Returns the LED state to send when it changed.
+    pub fn update(&mut self, now: Instant) -> Option<Led> {
+        let led = loop {
+            match &self.active {
+                Some(active) => match active.playback.led_at(now) {
+                    Some(led) => break Some(led),
+                    None => self.start_next(now),
+                },
+                None => {
+                    break self
+                        .ambient
+                        .as_ref()
+                        .map(|a| a.led_at(now).unwrap_or_else(|| a.last_led()))
+                }
+            }
+        };
+
+        match led {
+            Some(led) if self.led != Some(led) => {
+                self.led = Some(led);
+                Some(led)
+            }
+            _ => None,
+        }
+    }
+
+    fn start_next(&mut self, now: Instant) {
+        self.active = if self.queue.is_empty() {
+            None
+        } else {
+            let mut next = self.queue.remove(0);
+            next.playback.started = now;
+            Some(next)
+        };
+    }
+
+    /// Queues behind notifications of the same or higher priority.
+    fn enqueue(&mut self, notification: Notification) {
+        let index = self
+            .queue
+            .iter()
+            .position(|n| n.priority < notification.priority)
+            .unwrap_or(self.queue.len());
+        self.queue.insert(index, notification);
+    }
+
+    /// Queues ahead of notifications of the same priority.
+    fn enqueue_front(&mut self, notification: Notification) {
+        let index = self
+            .queue
+            .iter()
+            .position(|n| n.priority <= notification.priority)
+            .unwrap_or(self.queue.len());
+        self.queue.insert(index, notification);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn plays_ambient_pattern() {
+        let start = Instant::now();
+        let mut scheduler = LedScheduler::new();
+        assert_eq!(scheduler.update(start), None);
+
+        scheduler.set_ambient(LedPattern::pulse(100, 300), start);
+        assert_eq!(scheduler.update(at(start, 0)), Some(Led::On));
+        assert_eq!(scheduler.update(at(start, 50)), None);
+        assert_eq!(scheduler.update(at(start, 100)), Some(Led::Off));
+        assert_eq!(scheduler.update(at(start, 450)), Some(Led::On));
+    }
+
+    #[test]
+    fn notification_overrides_and_restores_ambient() {
+        let start = Instant::now();
+        let mut scheduler = LedScheduler::new();
+        scheduler.set_ambient(LedPattern::solid(Led::On), start);
+        assert_eq!(scheduler.update(start), Some(Led::On));
+
+        scheduler.notify(LedPattern::double_blink(), 0, at(start, 10));
+        assert_eq!(scheduler.update(at(start, 20)), None);
+        assert_eq!(scheduler.update(at(start, 140)), Some(Led::Off));
+        assert_eq!(scheduler.update(at(start, 260)), Some(Led::On));
+        assert_eq!(scheduler.update(at(start, 380)), Some(Led::Off));
+        assert_eq!(scheduler.update(at(start, 769)), None);
+        assert_eq!(scheduler.update(at(start, 770)), Some(Led::On));
+    }
+
+    #[test]
+    fn higher_priority_interrupts_and_lower_resumes() {
+        let start = Instant::now();
+        let mut scheduler = LedScheduler::new();
+        let once = |led| LedPattern {
+            steps: vec![LedStep::new(led, 100)],
+            repeat: Some(1),
+        };
+
+        scheduler.notify(once(Led::On), 1, start);
+        assert_eq!(scheduler.update(start), Some(Led::On));
+        scheduler.notify(once(Led::Blink), 5, at(start, 50));
+        assert_eq!(scheduler.update(at(start, 50)), Some(Led::Blink));
+        scheduler.notify(once(Led::Off), 0, at(start, 60));
+
+        // The interrupted notification plays again in full before the lower priority one
+        assert_eq!(scheduler.update(at(start, 150)), Some(Led::On));
+        assert_eq!(scheduler.update(at(start, 249)), None);
+        assert_eq!(scheduler.update(at(start, 250)), Some(Led::Off));
+    }
+
+    #[test]
+    fn breathing_keeps_the_period() {
+        let pattern = LedPattern::breathing(3200);
+        assert_eq!(pattern.cycle(), Duration::from_millis(3200));
+        assert_eq!(pattern.steps.first(), Some(&LedStep::new(Led::On, 25)));
+    }
+}
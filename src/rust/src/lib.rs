@@ -4,16 +4,73 @@
  */
 
 
-use hidapi::HidApi;
+use clock::{Clock, SystemClock};
+use decoder::DecoderState;
+use descriptor::ReportDescriptor;
+use dispatch::{Callback, CallbackKind, ErrorCallback, FailurePolicy, Subscribers};
+use explore::{ExplorationReport, ProtocolExplorer, SweepConfig, SweepStep};
+use filter::{EventFilter, FilterChain};
+use led::{LedPattern, LedScheduler};
+use metrics::{Metrics, MetricsSnapshot};
+use settings::{ControllerSettings, SettingsFile, Shaping};
 use std::error::Error;
 use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
-use types::{Button, SystemEvent, Wheel};
+use std::time::{Duration, Instant};
+use transport::Transport;
+use types::{Button, ControllerState, Event, Led, PanelOutput, SystemEvent, Wheel};
 
+pub mod accumulator;
+pub mod arc_menu;
+pub mod clock;
+pub mod decoder;
+pub mod descriptor;
+pub mod dispatch;
+pub mod explore;
+pub mod filter;
+pub mod gesture;
+pub mod led;
+pub mod metrics;
+pub mod settings;
+pub mod transport;
 pub mod types;
+pub mod virtual_panel;
+
+/// How often the LED patterns and the idle timeout are checked.
+const OUTPUT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Wait after the first failed read; doubled after every further failure up to [`READ_BACKOFF_MAX`].
+const READ_BACKOFF: Duration = Duration::from_millis(10);
+const READ_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// Failed reads in a row after which the read thread gives up.
+const READ_RETRIES: u32 = 10;
+
+/// Callback invoked for every input report read from the device.
+pub type DeviceEventCallback = Callback<SystemEvent>;
+/// Callback invoked when a wheel moves.
+pub type WheelEventCallback = Callback<(Wheel, u8)>;
+/// Callback invoked when a button is pressed.
+pub type ButtonEventCallback = Callback<Button>;
+/// Callback invoked for every decoded event, including button releases.
+pub type EventCallback = Callback<Event>;
+
+/// `InputState` is the input as delivered to the callbacks, for [`ControllerState`].
+#[derive(Debug, Copy, Clone, Default)]
+struct InputState {
+    button_held: Button,
+    angular_position: Option<u8>,
+    front_wheel_moved: Option<Instant>,
+    angular_wheel_moved: Option<Instant>,
+    back_wheel_moved: Option<Instant>,
+}
+
+/// The transport, shared between the read thread and writers.
+type SharedTransport = Arc<Mutex<Box<dyn Transport>>>;
 
 /// `Beolyd5Controller` is a struct that represents a BeoSound 5 controller.
 /// It provides methods to open the device, send commands, and register callbacks for device events.
@@ -21,16 +78,35 @@ pub struct Beolyd5Controller {
     threads: Vec<JoinHandle<Result<(), Box<dyn Error + Send>>>>,
     vendor_id: u16,
     product_id: u16,
-    last_read: Arc<Mutex<[u8; 6]>>,
-    last_button_pressed: Arc<Mutex<Button>>,
-    last_front_wheel_pos: Arc<Mutex<u8>>,
-    last_angular_wheel_pos: Arc<Mutex<u8>>,
-    last_back_wheel_pos: Arc<Mutex<u8>>,
+    decoder_state: Arc<Mutex<DecoderState>>,
+    explorer: Arc<Mutex<Option<ProtocolExplorer>>>,
     is_running: Arc<AtomicBool>,
-    device_event_callbacks: Vec<Arc<Mutex<dyn Fn(SystemEvent) -> Result<(), Box<dyn Error + Send>> + Send>>>,
-    wheel_event_callbacks: Vec<Arc<Mutex<dyn Fn((Wheel, u8))-> Result<(), Box<dyn Error + Send>> + Send>>>,
-    button_event_callbacks: Vec<Arc<Mutex<dyn Fn(Button)-> Result<(), Box<dyn Error + Send>> + Send>>>,
-    device: Option<Arc<Mutex<hidapi::HidDevice>>>,
+    device_event_callbacks: Arc<Mutex<Subscribers<SystemEvent>>>,
+    wheel_event_callbacks: Arc<Mutex<Subscribers<(Wheel, u8)>>>,
+    button_event_callbacks: Arc<Mutex<Subscribers<Button>>>,
+    event_callbacks: Arc<Mutex<Subscribers<Event>>>,
+    error_callbacks: Arc<Mutex<Vec<ErrorCallback>>>,
+    failure_policy: Arc<Mutex<FailurePolicy>>,
+    metrics: Arc<Metrics>,
+    led_scheduler: Arc<Mutex<LedScheduler>>,
+    output: Arc<Mutex<PanelOutput>>,
+    settings: Arc<Mutex<ControllerSettings>>,
+    settings_path: Option<PathBuf>,
+    shaping: Arc<Mutex<Shaping>>,
+    filters: Arc<Mutex<FilterChain>>,
+    last_input: Arc<Mutex<Instant>>,
+    input_state: Arc<Mutex<InputState>>,
+    idle: Arc<AtomicBool>,
+    serial_number: Arc<Mutex<Option<String>>>,
+    check_descriptor: bool,
+    clock: Arc<dyn Clock>,
+    device: Option<SharedTransport>,
+}
+
+impl Default for Beolyd5Controller {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Beolyd5Controller {
@@ -40,45 +116,117 @@ impl Beolyd5Controller {
             threads: Vec::new(),
             vendor_id: 0x0cd4,
             product_id: 0x1112,
-            last_read: Arc::new(Mutex::new([0u8; 6])),
-            last_button_pressed: Arc::new(Mutex::new(Button::None)),
-            last_front_wheel_pos: Arc::new(Mutex::new(0)),
-            last_angular_wheel_pos: Arc::new(Mutex::new(0)),
-            last_back_wheel_pos: Arc::new(Mutex::new(0)),
+            decoder_state: Arc::new(Mutex::new(DecoderState::default())),
+            explorer: Arc::new(Mutex::new(None)),
             is_running: Arc::new(AtomicBool::new(false)),
-            device_event_callbacks: Vec::new(),
-            wheel_event_callbacks: Vec::new(),
-            button_event_callbacks: Vec::new(),
+            device_event_callbacks: Arc::new(Mutex::new(Subscribers::new(CallbackKind::Device))),
+            wheel_event_callbacks: Arc::new(Mutex::new(Subscribers::new(CallbackKind::Wheel))),
+            button_event_callbacks: Arc::new(Mutex::new(Subscribers::new(CallbackKind::Button))),
+            event_callbacks: Arc::new(Mutex::new(Subscribers::new(CallbackKind::Event))),
+            error_callbacks: Arc::new(Mutex::new(Vec::new())),
+            failure_policy: Arc::new(Mutex::new(FailurePolicy::default())),
+            metrics: Arc::new(Metrics::new()),
+            led_scheduler: Arc::new(Mutex::new(LedScheduler::new())),
+            output: Arc::new(Mutex::new(PanelOutput::default())),
+            settings: Arc::new(Mutex::new(ControllerSettings::default())),
+            settings_path: settings::default_path(),
+            shaping: Arc::new(Mutex::new(Shaping::default())),
+            filters: Arc::new(Mutex::new(FilterChain::new())),
+            last_input: Arc::new(Mutex::new(Instant::now())),
+            input_state: Arc::new(Mutex::new(InputState::default())),
+            idle: Arc::new(AtomicBool::new(false)),
+            serial_number: Arc::new(Mutex::new(None)),
+            check_descriptor: true,
+            clock: Arc::new(SystemClock),
             device: None,
         }
     }
 
-    /// Opens the device and starts a new thread to handle device events.
-    /// Returns `Ok(())` if the device was opened successfully, or an `Err` if the device could not be found or accessed.
+    /// Creates a new `Beolyd5Controller` that talks to the panel through `transport` instead of
+    /// opening the default one.
+    pub fn with_transport<T: Transport + 'static>(transport: T) -> Beolyd5Controller {
+        let mut controller = Beolyd5Controller::new();
+        controller.device = Some(Arc::new(Mutex::new(Box::new(transport))));
+        controller
+    }
+
+    /// Opens the device, applies its settings and starts a new thread to handle device events.
+    /// Returns `Ok(())` if the device was opened successfully, or an `Err` if the device could not be
+    /// found or accessed, or declares other reports than expected (see [`set_check_descriptor`](Beolyd5Controller::set_check_descriptor)).
+    /// A settings file that cannot be read is logged and the default settings are used, see
+    /// [`load_settings`](Beolyd5Controller::load_settings).
     pub fn open(&mut self) -> Result<(), Box<dyn Error>> {
         let is_running = self.is_running.clone();
 
         if self.device.is_none() {
-            let api = HidApi::new()?;
-            let device = match api.open(self.vendor_id, self.product_id) {
-                Ok(device) => device,
-                Err(_) => return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "BS5 controller not found"))),
-            };
+            let device = transport::open_default(self.vendor_id, self.product_id)?;
             self.device = Some(Arc::new(Mutex::new(device)));
         }
+        if self.check_descriptor {
+            let raw = self
+                .device
+                .as_ref()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .report_descriptor();
+            match raw {
+                Ok(raw) => ReportDescriptor::parse(&raw)?.check()?,
+                Err(err) => eprintln!("Could not check the report descriptor: {}", err),
+            }
+        }
+
+        let serial_number = self
+            .device
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .serial_number();
+        *self.serial_number.lock().unwrap() = serial_number;
+        // A broken settings file must not take the panel down with it
+        if let Err(err) = self.load_settings() {
+            eprintln!("Failed to load settings, using the defaults: {}", err);
+            self.set_settings(ControllerSettings::default());
+        }
+        *self.last_input.lock().unwrap() = self.clock.now();
 
+        self.metrics.opened();
         self.is_running.store(true, Ordering::Relaxed);
         let device_clone = self.device.clone().unwrap();
         let self_ref = Arc::new(self.clone());
 
         let t = thread::spawn(move || -> Result<(), Box<dyn Error + Send>> {
-            let mut buffer = [0u8; 6];
+            let mut buffer = [0u8; decoder::REPORT_LEN];
+            let mut failures = 0;
             while is_running.load(Ordering::Relaxed) {
-                let device_lock = device_clone.lock().unwrap();
-                let result = device_lock.read(&mut buffer[..]).unwrap();
+                let mut device_lock = device_clone.lock().unwrap();
+                // Time out regularly so writes from other threads get a chance at the device.
+                let result = device_lock.read_timeout(&mut buffer[..], 100);
+                self_ref
+                    .metrics
+                    .transport_reconnects(device_lock.reconnects());
                 drop(device_lock);
+                let result = match result {
+                    Ok(result) => {
+                        failures = 0;
+                        result
+                    }
+                    Err(e) => {
+                        // Retry transient errors, e.g. while a remote panel reconnects
+                        self_ref.metrics.read_failed();
+                        failures += 1;
+                        if failures >= READ_RETRIES {
+                            return Err(Box::new(e) as Box<dyn Error + Send>);
+                        }
+                        let backoff = READ_BACKOFF * 2u32.pow(failures - 1);
+                        thread::sleep(backoff.min(READ_BACKOFF_MAX));
+                        continue;
+                    }
+                };
                 if result > 0 {
-                    self_ref.handle_device_event(buffer)?;
+                    self_ref.metrics.report_read(self_ref.clock.now());
+                    self_ref.handle_device_event(buffer);
                 }
             }
 
@@ -86,6 +234,18 @@ impl Beolyd5Controller {
         });
         self.threads.push(t);
 
+        let is_running = self.is_running.clone();
+        let self_ref = Arc::new(self.clone());
+        let t = thread::spawn(move || -> Result<(), Box<dyn Error + Send>> {
+            while is_running.load(Ordering::Relaxed) {
+                self_ref.update_outputs();
+                thread::sleep(OUTPUT_INTERVAL);
+            }
+
+            Ok(())
+        });
+        self.threads.push(t);
+
         Ok(())
     }
 
@@ -124,140 +284,452 @@ impl Beolyd5Controller {
     /// - `[0x80, 0x00]` to turn off the LCD backlight and turn on the LED
     /// - `[0xd0, 0x00]` to make the LED blink
     /// - `[0x01, 0x00]` to make a click sound
+    ///
     /// Returns `Ok(())` if the command was sent successfully, or an `Err` if there was a problem sending the command.
     pub fn send(&self, data: [u8; 2]) -> Result<(), Box<dyn Error>> {
-        let device_clone = self.device.clone().ok_or_else(|| {
-            Box::new(std::io::Error::new(
-                ErrorKind::NotFound,
-                "BS5 controller not found or not accessible",
-            ))
-        })?;
-        let device_lock = device_clone.lock().unwrap();
-        device_lock.write(&data[..])?;
+        let device_clone = self.device()?;
+        let started = self.clock.now();
+        self.metrics.write_queued();
+        let mut device_lock = device_clone.lock().unwrap();
+        let result = device_lock.write(&data[..]);
+        drop(device_lock);
+        self.metrics
+            .write_done(self.clock.now().saturating_duration_since(started));
+        result?;
+        *self.output.lock().unwrap() = PanelOutput::from_report(data);
 
         Ok(())
     }
 
+    /// Sets whether [`open`](Beolyd5Controller::open) checks the reports declared in the HID report
+    /// descriptor against the 6-byte input and 2-byte output report the controller uses. On by
+    /// default; a transport that cannot read the descriptor is logged and not checked.
+    pub fn set_check_descriptor(&mut self, check: bool) {
+        self.check_descriptor = check;
+    }
+
+    /// Sets the clock used for the idle timeout, LED patterns and metrics, see [`clock`].
+    /// Defaults to [`SystemClock`].
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        *self.last_input.lock().unwrap() = clock.now();
+        self.clock = clock;
+    }
+
+    /// Reads and parses the HID report descriptor of the panel, see [`descriptor`].
+    pub fn report_descriptor(&self) -> Result<ReportDescriptor, Box<dyn Error>> {
+        let raw = self.device()?.lock().unwrap().report_descriptor()?;
+        Ok(ReportDescriptor::parse(&raw)?)
+    }
+
+    /// Reads the feature report `id` (`0` for unnumbered reports) of up to `len` bytes.
+    /// Returns the report without the report ID.
+    pub fn get_feature_report(&self, id: u8, len: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut buf = vec![0u8; len + 1];
+        buf[0] = id;
+        let read = self
+            .device()?
+            .lock()
+            .unwrap()
+            .get_feature_report(&mut buf)?;
+        buf.truncate(read.max(1));
+        buf.remove(0);
+        Ok(buf)
+    }
+
+    /// Writes `data` as the feature report `id` (`0` for unnumbered reports).
+    pub fn send_feature_report(&self, id: u8, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut buf = Vec::with_capacity(data.len() + 1);
+        buf.push(id);
+        buf.extend_from_slice(data);
+        self.device()?.lock().unwrap().send_feature_report(&buf)?;
+        Ok(())
+    }
+
+    /// Switches the LED, keeping the backlight as it was last sent.
+    pub fn set_led(&self, led: Led) -> Result<(), Box<dyn Error>> {
+        let output = PanelOutput {
+            led,
+            click: false,
+            sound: 0,
+            ..*self.output.lock().unwrap()
+        };
+        self.send(output.to_report())
+    }
+
+    /// Turns the LCD backlight on or off, keeping the LED as it was last sent.
+    pub fn set_backlight(&self, on: bool) -> Result<(), Box<dyn Error>> {
+        let output = PanelOutput {
+            backlight: on,
+            click: false,
+            sound: 0,
+            ..*self.output.lock().unwrap()
+        };
+        self.send(output.to_report())
+    }
+
+    /// Makes the panel click, keeping the LED and backlight as they were last sent.
+    pub fn click(&self) -> Result<(), Box<dyn Error>> {
+        let output = PanelOutput {
+            click: true,
+            sound: 0,
+            ..*self.output.lock().unwrap()
+        };
+        self.send(output.to_report())
+    }
+
+    /// Plays the sound `code`, keeping the LED and backlight as they were last sent.
+    /// See `examples/listen.rs` for the sounds found so far.
+    pub fn play_sound(&self, code: u8) -> Result<(), Box<dyn Error>> {
+        let output = PanelOutput {
+            click: false,
+            sound: code,
+            ..*self.output.lock().unwrap()
+        };
+        self.send(output.to_report())
+    }
+
+    /// Sets the LED pattern shown whenever no notification is playing, see [`led`].
+    /// The pattern is played while the controller is open.
+    pub fn set_led_pattern(&self, pattern: LedPattern) {
+        self.led_scheduler
+            .lock()
+            .unwrap()
+            .set_ambient(pattern, self.clock.now());
+    }
+
+    /// Plays `pattern` on the LED, interrupting the ambient pattern and notifications of lower priority.
+    pub fn notify_led(&self, pattern: LedPattern, priority: u8) {
+        self.led_scheduler
+            .lock()
+            .unwrap()
+            .notify(pattern, priority, self.clock.now());
+    }
+
+    /// Stops all LED notifications and goes back to the ambient pattern.
+    pub fn clear_led_notifications(&self) {
+        self.led_scheduler.lock().unwrap().clear_notifications();
+    }
+
     /// Closes the device and stops handling device events.
     pub fn close(&self) {
         self.is_running.store(false, Ordering::Relaxed);
     }
 
+    /// Returns `true` between [`open`](Beolyd5Controller::open) and [`close`](Beolyd5Controller::close).
+    pub fn is_open(&self) -> bool {
+        self.is_running.load(Ordering::Relaxed)
+    }
+
+    /// Starts recording statistics about every bit of the input reports, see [`explore`].
+    /// Already recorded statistics are kept if exploration is enabled already.
+    pub fn enable_exploration(&self) {
+        let mut explorer = self.explorer.lock().unwrap();
+        if explorer.is_none() {
+            *explorer = Some(ProtocolExplorer::new());
+        }
+    }
+
+    /// Stops recording input report statistics and discards what has been recorded.
+    pub fn disable_exploration(&self) {
+        *self.explorer.lock().unwrap() = None;
+    }
+
+    /// Returns the statistics recorded since exploration was enabled, or `None` if it is disabled.
+    pub fn exploration_report(&self) -> Option<ExplorationReport> {
+        self.explorer.lock().unwrap().as_ref().map(|e| e.report())
+    }
+
+    /// Walks through the output report values described by `config`, holding each for `config.dwell`.
+    /// Enables exploration if needed, records which input bits changed while each value was held,
+    /// and hands every step to `log` as soon as it completes. The baseline report is restored afterwards.
+    /// Returns all steps, or an `Err` if a command could not be sent.
+    pub fn sweep_output<F>(
+        &self,
+        config: &SweepConfig,
+        mut log: F,
+    ) -> Result<Vec<SweepStep>, Box<dyn Error>>
+    where
+        F: FnMut(&SweepStep),
+    {
+        if config.byte >= config.baseline.len() {
+            return Err(Box::new(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "sweep byte must be 0 or 1",
+            )));
+        }
+        self.enable_exploration();
+
+        let mut steps = Vec::new();
+        for value in config.from..=config.to {
+            let mut output = config.baseline;
+            output[config.byte] = value;
+
+            let before = self.exploration_report().unwrap_or_default();
+            self.send(output)?;
+            thread::sleep(config.dwell);
+            let after = self.exploration_report().unwrap_or_default();
+
+            let step = SweepStep {
+                output,
+                reports: after.reports - before.reports,
+                changed_bits: explore::changed_bits(&before, &after),
+            };
+            log(&step);
+            steps.push(step);
+        }
+
+        self.send(config.baseline)?;
+        Ok(steps)
+    }
+
     /// Registers a callback to be called when any device event occurs.
-    pub fn register_device_event_callback(
-        &mut self,
-        callback: Arc<Mutex<dyn Fn(SystemEvent) -> Result<(), Box<dyn Error + Send>> + Send>>,
-    ) {
-        self.device_event_callbacks.push(callback);
+    pub fn register_device_event_callback(&mut self, callback: DeviceEventCallback) {
+        self.device_event_callbacks.lock().unwrap().push(callback);
     }
 
     /// Registers a callback to be called when a wheel event occurs.
-    pub fn register_wheel_event_callback(
-        &mut self,
-        callback: Arc<Mutex<dyn Fn((Wheel, u8)) -> Result<(), Box<dyn Error + Send>> + Send>>,
-    ) {
-        self.wheel_event_callbacks.push(callback);
+    pub fn register_wheel_event_callback(&mut self, callback: WheelEventCallback) {
+        self.wheel_event_callbacks.lock().unwrap().push(callback);
     }
 
     /// Registers a callback to be called when a button event occurs.
-    pub fn register_button_event_callback(&mut self, callback: Arc<Mutex<dyn Fn(Button) -> Result<(), Box<dyn Error + Send>> + Send>>) {
-        self.button_event_callbacks.push(callback);
+    pub fn register_button_event_callback(&mut self, callback: ButtonEventCallback) {
+        self.button_event_callbacks.lock().unwrap().push(callback);
     }
 
-    fn handle_device_event(&self, event: [u8; 6]) -> Result<(), Box<dyn Error + Send>> {
-        let wheel_changed = Self::get_wheel_moved(event, self.last_read.lock().unwrap().clone());
-        let button_pressed = Self::get_button_pressed(event);
+    /// Registers a callback to be called for every decoded [`Event`], after the wheel and button callbacks.
+    pub fn register_event_callback(&mut self, callback: EventCallback) {
+        self.event_callbacks.lock().unwrap().push(callback);
+    }
 
-        let top_wheel_pos = event[0];
-        let angular_wheel_pos = event[2];
-        let back_wheel_pos = event[1];
+    /// Adds a stage at the end of the event filter chain, see [`filter`]. Stages see every decoded
+    /// event before the wheel, button and event callbacks do.
+    pub fn add_filter(&self, filter: impl EventFilter + 'static) {
+        self.filters.lock().unwrap().push(filter);
+    }
 
-        if wheel_changed.0 != Wheel::None {
-            self.handle_wheel_event(event)?;
-        } else if button_pressed != Button::None {
-            self.handle_button_event(event)?;
-        }
+    /// Removes all event filter stages.
+    pub fn clear_filters(&self) {
+        self.filters.lock().unwrap().clear();
+    }
 
-        *self.last_read.lock().unwrap() = event;
-        *self.last_button_pressed.lock().unwrap() = button_pressed;
-        *self.last_front_wheel_pos.lock().unwrap() = top_wheel_pos;
-        *self.last_angular_wheel_pos.lock().unwrap() = angular_wheel_pos;
-        *self.last_back_wheel_pos.lock().unwrap() = back_wheel_pos;
+    /// Registers a callback to be called when another callback returns an `Err` or panics.
+    /// Without any error callbacks, such failures are printed to stderr.
+    /// A failing callback never stops event delivery to the other callbacks.
+    pub fn register_error_callback(&mut self, callback: ErrorCallback) {
+        self.error_callbacks.lock().unwrap().push(callback);
+    }
 
-        let device_event_callbacks = self.device_event_callbacks.clone();
-        let last_read_clone = self.last_read.lock().unwrap().clone();
-        let sys_event = SystemEvent {
-            event_bytes: event,
-            last_read_bytes: last_read_clone,
-            front_wheel_pos: top_wheel_pos,
-            back_wheel_pos,
-            angular_wheel_pos,
-            button_pressed,
-        };
+    /// Sets what happens to callbacks that keep failing, see [`FailurePolicy`].
+    pub fn set_failure_policy(&self, policy: FailurePolicy) {
+        *self.failure_policy.lock().unwrap() = policy;
+    }
 
-        for callback in &device_event_callbacks {
-            let callback = callback.lock().unwrap();
-            callback(sys_event.clone())?;
-        }
+    /// Sets the settings file read by [`open`](Beolyd5Controller::open) and written by
+    /// [`save_settings`](Beolyd5Controller::save_settings). Defaults to [`settings::default_path`];
+    /// `None` disables loading and saving.
+    pub fn set_settings_path(&mut self, path: Option<&Path>) {
+        self.settings_path = path.map(Path::to_path_buf);
+    }
 
+    /// Reads the settings of the panel from the settings file and applies them. Fails if the file
+    /// cannot be read or parsed; a missing file or no settings file applies the defaults.
+    pub fn load_settings(&self) -> Result<(), Box<dyn Error>> {
+        let settings = match &self.settings_path {
+            Some(path) => SettingsFile::load(path)?.get(self.serial_number().as_deref()),
+            None => ControllerSettings::default(),
+        };
+        self.set_settings(settings);
         Ok(())
     }
 
-    fn handle_wheel_event(&self, event: [u8; 6]) -> Result<(), Box<dyn Error + Send>> {
-        let wheel_changed = Self::get_wheel_moved(event, self.last_read.lock().unwrap().clone());
-        if wheel_changed.0 != Wheel::None {
-            for callback in &self.wheel_event_callbacks {
-                let callback = callback.lock().unwrap();
-                callback(wheel_changed)?;
-            }
+    /// Returns the settings currently applied.
+    pub fn settings(&self) -> ControllerSettings {
+        *self.settings.lock().unwrap()
+    }
+
+    /// Applies `settings` right away. Use [`save_settings`](Beolyd5Controller::save_settings) to keep them.
+    pub fn set_settings(&self, settings: ControllerSettings) {
+        *self.settings.lock().unwrap() = settings;
+    }
+
+    /// Stores the settings currently applied in the settings file, under the serial number of the panel.
+    /// Settings of other panels in the file are kept.
+    pub fn save_settings(&self) -> Result<(), Box<dyn Error>> {
+        let path = self.settings_path.as_ref().ok_or_else(|| {
+            Box::new(std::io::Error::new(
+                ErrorKind::NotFound,
+                "no settings file configured",
+            ))
+        })?;
+
+        let mut file = SettingsFile::load(path)?;
+        file.set(self.serial_number().as_deref(), self.settings());
+        file.save(path)
+    }
+
+    /// Returns the serial number of the opened panel, if the transport reports one.
+    pub fn serial_number(&self) -> Option<String> {
+        self.serial_number.lock().unwrap().clone()
+    }
+
+    /// Returns a snapshot of the panel state: buttons, pointer position, wheel activity, outputs
+    /// and whether the controller is open. Useful to set up a view without waiting for input.
+    pub fn state(&self) -> ControllerState {
+        let input = *self.input_state.lock().unwrap();
+        ControllerState {
+            open: self.is_open(),
+            serial_number: self.serial_number(),
+            button_held: input.button_held,
+            angular_position: input.angular_position,
+            front_wheel_moved: input.front_wheel_moved,
+            angular_wheel_moved: input.angular_wheel_moved,
+            back_wheel_moved: input.back_wheel_moved,
+            last_input: *self.last_input.lock().unwrap(),
+            idle: self.idle.load(Ordering::Relaxed),
+            output: *self.output.lock().unwrap(),
         }
+    }
 
-        Ok(())
+    /// Returns the current runtime metrics, see [`metrics`].
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot(self.clock.now())
     }
 
-    /*
-     * Front and back wheels are only untouched if they are 0
-     * Angular wheel is only untouched if it is the same as the last reading
-     */
-    fn get_wheel_moved(event: [u8; 6], last_read: [u8; 6]) -> (Wheel, u8) {
-        let front_wheel_pos = event[0];
-        let angular_wheel_pos = event[2];
-        let back_wheel_pos = event[1];
-
-        return if front_wheel_pos != 0 {
-            (Wheel::Front, front_wheel_pos)
-        } else if last_read[2] != angular_wheel_pos {
-            (Wheel::Angular, angular_wheel_pos)
-        } else if back_wheel_pos != 0 {
-            (Wheel::Back, back_wheel_pos)
-        } else {
-            (Wheel::None, 0)
-        };
+    fn device(&self) -> Result<SharedTransport, Box<dyn Error>> {
+        self.device.clone().ok_or_else(|| {
+            Box::new(std::io::Error::new(
+                ErrorKind::NotFound,
+                "BS5 controller not found or not accessible",
+            )) as Box<dyn Error>
+        })
     }
 
-    fn handle_button_event(&self, event: [u8; 6]) -> Result<(), Box<dyn Error + Send>> {
-        let button_pressed = Self::get_button_pressed(event);
+    /// Records a delivered event for [`state`](Beolyd5Controller::state).
+    fn track_input(&self, event: Event, now: Instant) {
+        let mut input = self.input_state.lock().unwrap();
+        match event {
+            Event::WheelMoved(Wheel::Front, _) => input.front_wheel_moved = Some(now),
+            Event::WheelMoved(Wheel::Angular, pos) => {
+                input.angular_position = Some(pos);
+                input.angular_wheel_moved = Some(now);
+            }
+            Event::WheelMoved(Wheel::Back, _) => input.back_wheel_moved = Some(now),
+            Event::WheelMoved(Wheel::None, _) => (),
+            Event::ButtonPressed(button) => input.button_held = button,
+            Event::ButtonReleased(button) => {
+                if input.button_held == button {
+                    input.button_held = Button::None;
+                }
+            }
+        }
+    }
 
-        if *self.last_button_pressed.lock().unwrap() != button_pressed {
-            *self.last_button_pressed.lock().unwrap() = button_pressed;
-            for callback in &self.button_event_callbacks {
-                let callback = callback.lock().unwrap();
-                callback(button_pressed)?;
+    /// Plays the LED patterns and applies the idle timeout, as of the controller's clock.
+    fn update_outputs(&self) {
+        let led = self.led_scheduler.lock().unwrap().update(self.clock.now());
+        if let Some(led) = led {
+            if let Err(err) = self.set_led(led) {
+                eprintln!("Failed to update LED: {:?}", err);
             }
         }
+        if let Err(err) = self.check_idle() {
+            eprintln!("Failed to turn off backlight: {:?}", err);
+        }
+    }
 
+    /// Turns the backlight off once the idle timeout passed without input.
+    fn check_idle(&self) -> Result<(), Box<dyn Error>> {
+        let Some(timeout) = self.settings().idle_timeout_secs else {
+            return Ok(());
+        };
+        let idle_for = self
+            .clock
+            .now()
+            .saturating_duration_since(*self.last_input.lock().unwrap());
+        if idle_for >= Duration::from_secs(timeout) && !self.idle.swap(true, Ordering::Relaxed) {
+            self.set_backlight(false)?;
+        }
         Ok(())
     }
 
-    fn get_button_pressed(event: [u8; 6]) -> Button {
-        return match event[3] {
-            0x00 => Button::None,
-            0x20 => Button::Left,
-            0x10 => Button::Right,
-            0x40 => Button::Go,
-            0x80 => Button::Standby,
-            _ => Button::None,
+    fn handle_device_event(&self, event: [u8; 6]) {
+        *self.last_input.lock().unwrap() = self.clock.now();
+        if self.idle.swap(false, Ordering::Relaxed) {
+            if let Err(err) = self.set_backlight(true) {
+                eprintln!("Failed to turn on backlight: {:?}", err);
+            }
+        }
+
+        let mut state = self.decoder_state.lock().unwrap();
+        let last_read = state.last_report;
+        let (events, next_state) = decoder::decode(event, &state);
+        *state = next_state;
+        drop(state);
+
+        if let Some(explorer) = self.explorer.lock().unwrap().as_mut() {
+            explorer.observe(event, &events);
+        }
+
+        let started = self.clock.now();
+        let policy = *self.failure_policy.lock().unwrap();
+        let settings = self.settings();
+        let now = self.clock.now();
+        for decoded in events {
+            let Some(decoded) = self.shaping.lock().unwrap().apply(&settings, decoded) else {
+                continue;
+            };
+            let Some(decoded) = self.filters.lock().unwrap().apply(decoded, now) else {
+                continue;
+            };
+            self.track_input(decoded, now);
+            match decoded {
+                Event::WheelMoved(wheel, pos) => dispatch::dispatch(
+                    &self.wheel_event_callbacks,
+                    (wheel, pos),
+                    policy,
+                    &self.error_callbacks,
+                ),
+                Event::ButtonPressed(button) => {
+                    if settings.click_on_press {
+                        if let Err(err) = self.click() {
+                            eprintln!("Failed to click: {:?}", err);
+                        }
+                    }
+                    dispatch::dispatch(
+                        &self.button_event_callbacks,
+                        button,
+                        policy,
+                        &self.error_callbacks,
+                    )
+                }
+                Event::ButtonReleased(_) => (),
+            }
+            dispatch::dispatch(
+                &self.event_callbacks,
+                decoded,
+                policy,
+                &self.error_callbacks,
+            );
+        }
+
+        let sys_event = SystemEvent {
+            event_bytes: event,
+            last_read_bytes: last_read,
+            front_wheel_pos: event[decoder::FRONT_WHEEL_BYTE],
+            back_wheel_pos: event[decoder::BACK_WHEEL_BYTE],
+            angular_wheel_pos: event[decoder::ANGULAR_WHEEL_BYTE],
+            button_pressed: decoder::button_pressed(event),
         };
+
+        dispatch::dispatch(
+            &self.device_event_callbacks,
+            sys_event,
+            policy,
+            &self.error_callbacks,
+        );
+        self.metrics
+            .dispatched(self.clock.now().saturating_duration_since(started));
     }
 }
 
@@ -282,16 +754,280 @@ impl Clone for Beolyd5Controller {
             threads: Vec::new(),
             vendor_id: self.vendor_id,
             product_id: self.product_id,
-            last_read: self.last_read.clone(),
-            last_button_pressed: self.last_button_pressed.clone(),
-            last_front_wheel_pos: self.last_front_wheel_pos.clone(),
-            last_angular_wheel_pos: self.last_angular_wheel_pos.clone(),
-            last_back_wheel_pos: self.last_back_wheel_pos.clone(),
+            decoder_state: self.decoder_state.clone(),
+            explorer: self.explorer.clone(),
             is_running: self.is_running.clone(),
             device_event_callbacks: self.device_event_callbacks.clone(),
             wheel_event_callbacks: self.wheel_event_callbacks.clone(),
             button_event_callbacks: self.button_event_callbacks.clone(),
+            event_callbacks: self.event_callbacks.clone(),
+            error_callbacks: self.error_callbacks.clone(),
+            failure_policy: self.failure_policy.clone(),
+            metrics: self.metrics.clone(),
+            led_scheduler: self.led_scheduler.clone(),
+            output: self.output.clone(),
+            settings: self.settings.clone(),
+            settings_path: self.settings_path.clone(),
+            shaping: self.shaping.clone(),
+            filters: self.filters.clone(),
+            last_input: self.last_input.clone(),
+            input_state: self.input_state.clone(),
+            idle: self.idle.clone(),
+            serial_number: self.serial_number.clone(),
+            check_descriptor: self.check_descriptor,
+            clock: self.clock.clone(),
             device: self.device.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::ManualClock;
+    use std::io;
+    use virtual_panel::VirtualPanel;
+
+    fn controller(panel: &VirtualPanel, clock: &ManualClock) -> Beolyd5Controller {
+        let mut controller = Beolyd5Controller::with_transport(panel.transport().unwrap());
+        controller.set_settings_path(None);
+        controller.set_clock(Arc::new(clock.clone()));
+        controller
+    }
+
+    #[test]
+    fn idle_timeout_follows_the_clock() {
+        let panel = VirtualPanel::new();
+        let clock = ManualClock::new();
+        let controller = controller(&panel, &clock);
+        controller.set_settings(ControllerSettings {
+            idle_timeout_secs: Some(60),
+            ..ControllerSettings::default()
+        });
+        controller.set_backlight(true).unwrap();
+
+        clock.advance(Duration::from_secs(59));
+        controller.update_outputs();
+        assert!(panel.state().output.backlight);
+
+        clock.advance(Duration::from_secs(1));
+        controller.update_outputs();
+        assert!(!panel.state().output.backlight);
+
+        // Any input wakes the panel up and restarts the timeout
+        controller.handle_device_event([0x01, 0, 0, 0, 0, 0]);
+        assert!(panel.state().output.backlight);
+        clock.advance(Duration::from_secs(59));
+        controller.update_outputs();
+        assert!(panel.state().output.backlight);
+    }
+
+    #[test]
+    fn led_patterns_follow_the_clock() {
+        let panel = VirtualPanel::new();
+        let clock = ManualClock::new();
+        let controller = controller(&panel, &clock);
+        controller.set_led_pattern(LedPattern::pulse(500, 500));
+
+        let mut leds = Vec::new();
+        for _ in 0..4 {
+            controller.update_outputs();
+            leds.push(panel.state().output.led);
+            clock.advance(Duration::from_millis(500));
+        }
+        assert_eq!(leds, vec![Led::On, Led::Off, Led::On, Led::Off]);
+    }
+
+    #[test]
+    fn state_tracks_the_delivered_input() {
+        let panel = VirtualPanel::new();
+        let clock = ManualClock::new();
+        let controller = controller(&panel, &clock);
+        let start = clock.now();
+        assert_eq!(controller.state().angular_position, None);
+
+        controller.handle_device_event([0, 0, 0x30, 0x40, 0, 0]);
+        clock.advance(Duration::from_millis(100));
+        controller.handle_device_event([0, 0xff, 0x30, 0x40, 0, 0]);
+        controller.set_backlight(true).unwrap();
+
+        let state = controller.state();
+        assert!(!state.open);
+        assert_eq!(state.button_held, Button::Go);
+        assert_eq!(state.angular_position, Some(0x30));
+        assert_eq!(state.angular_wheel_moved, Some(start));
+        assert_eq!(
+            state.back_wheel_moved,
+            Some(start + Duration::from_millis(100))
+        );
+        assert_eq!(state.front_wheel_moved, None);
+        assert_eq!(state.last_input, start + Duration::from_millis(100));
+        assert!(state.output.backlight);
+
+        controller.handle_device_event([0, 0, 0x30, 0, 0, 0]);
+        assert_eq!(controller.state().button_held, Button::None);
+    }
+
+    #[test]
+    fn filters_run_before_the_callbacks() {
+        let panel = VirtualPanel::new();
+        let clock = ManualClock::new();
+        let mut controller = controller(&panel, &clock);
+        controller.add_filter(filter::Invert::new(Wheel::Front));
+        controller.add_filter(filter::Remap::new(vec![(Button::Go, Button::Left)], vec![]));
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        controller.register_event_callback(Arc::new(Mutex::new(
+            move |event: Event| -> Result<(), Box<dyn Error + Send>> {
+                sink.lock().unwrap().push(event);
+                Ok(())
+            },
+        )));
+
+        controller.handle_device_event([0x01, 0, 0, 0x40, 0, 0]);
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                Event::WheelMoved(Wheel::Front, 0xff),
+                Event::ButtonPressed(Button::Left)
+            ]
+        );
+    }
+
+    /// Fails a few reads, as a flaky link does, before delivering one report.
+    struct FlakyTransport {
+        failures: u32,
+        report: Option<[u8; decoder::REPORT_LEN]>,
+    }
+
+    impl Transport for FlakyTransport {
+        fn read_timeout(&mut self, buf: &mut [u8], _timeout_ms: i32) -> io::Result<usize> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionReset,
+                    "link dropped",
+                ));
+            }
+            match self.report.take() {
+                Some(report) => {
+                    buf[..report.len()].copy_from_slice(&report);
+                    Ok(report.len())
+                }
+                None => {
+                    thread::sleep(Duration::from_millis(10));
+                    Ok(0)
+                }
+            }
+        }
+
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            Ok(data.len())
+        }
+
+        fn reconnects(&self) -> u64 {
+            3 - u64::from(self.failures)
+        }
+    }
+
+    #[test]
+    fn reading_continues_after_failed_reads() {
+        let mut controller = Beolyd5Controller::with_transport(FlakyTransport {
+            failures: 3,
+            report: Some([0x01, 0, 0, 0x40, 0, 0]),
+        });
+        controller.set_settings_path(None);
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tx = Mutex::new(tx);
+        controller.register_wheel_event_callback(Arc::new(Mutex::new(
+            move |event: (Wheel, u8)| -> Result<(), Box<dyn Error + Send>> {
+                tx.lock().unwrap().send(event).unwrap();
+                Ok(())
+            },
+        )));
+
+        controller.open().unwrap();
+        let event = rx.recv_timeout(Duration::from_secs(5));
+        controller.close();
+
+        assert_eq!(event, Ok((Wheel::Front, 1)));
+        let metrics = controller.metrics();
+        assert_eq!(metrics.read_errors, 3);
+        assert_eq!(metrics.reconnects, 3);
+    }
+
+    #[test]
+    fn dispatch_time_follows_the_clock() {
+        let panel = VirtualPanel::new();
+        let clock = ManualClock::new();
+        let mut controller = controller(&panel, &clock);
+        let callback_clock = clock.clone();
+        controller.register_event_callback(Arc::new(Mutex::new(
+            move |_: Event| -> Result<(), Box<dyn Error + Send>> {
+                callback_clock.advance(Duration::from_millis(5));
+                Ok(())
+            },
+        )));
+
+        controller.handle_device_event([0x01, 0, 0, 0, 0, 0]);
+        controller.send([0x40, 0x00]).unwrap();
+
+        let metrics = controller.metrics();
+        assert_eq!(metrics.dispatch_duration.count, 1);
+        assert_eq!(metrics.dispatch_duration.sum, 0.005);
+        assert_eq!(metrics.write_duration.count, 1);
+        assert_eq!(metrics.write_duration.sum, 0.0);
+    }
+
+    #[test]
+    fn a_broken_settings_file_falls_back_to_the_defaults() {
+        let path = std::env::temp_dir().join(format!("beolyd5-broken-{}.toml", std::process::id()));
+        std::fs::write(&path, "[default]\nclick_on_press = maybe\n").unwrap();
+        let panel = VirtualPanel::new();
+        let mut controller = Beolyd5Controller::with_transport(panel.transport().unwrap());
+        controller.set_settings_path(Some(&path));
+        controller.set_settings(ControllerSettings {
+            click_on_press: true,
+            ..ControllerSettings::default()
+        });
+
+        controller.open().unwrap();
+        controller.close();
+        let loaded = controller.load_settings();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(loaded.is_err());
+        assert_eq!(controller.settings(), ControllerSettings::default());
+    }
+
+    /// Declares a 4-byte input report instead of the 6-byte one the controller reads.
+    struct NarrowTransport;
+
+    impl Transport for NarrowTransport {
+        fn read_timeout(&mut self, _buf: &mut [u8], _timeout_ms: i32) -> io::Result<usize> {
+            Ok(0)
+        }
+
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            Ok(data.len())
+        }
+
+        fn report_descriptor(&self) -> io::Result<Vec<u8>> {
+            Ok(vec![
+                0x75, 0x08, 0x95, 0x04, 0x81, 0x02, 0x95, 0x02, 0x91, 0x02,
+            ])
+        }
+    }
+
+    #[test]
+    fn open_checks_the_descriptor_by_default() {
+        let mut controller = Beolyd5Controller::with_transport(NarrowTransport);
+        controller.set_settings_path(None);
+        assert!(controller.open().is_err());
+
+        controller.set_check_descriptor(false);
+        controller.open().unwrap();
+        controller.close();
+    }
+}
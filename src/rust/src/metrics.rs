@@ -0,0 +1,358 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Runtime metrics for the controller pipeline.
+//!
+//! The controller counts what happens between the USB device and the callbacks, so a laggy wheel
+//! can be traced to the device (few reports, read errors, reconnects) or to the application (slow
+//! callbacks, writes queueing up). Read them with
+//! [`Beolyd5Controller::metrics`](crate::Beolyd5Controller::metrics) and export them with
+//! [`MetricsSnapshot::to_prometheus`].
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Upper bounds, in seconds, of the latency histogram buckets.
+pub const LATENCY_BUCKETS: [f64; 12] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0,
+];
+
+/// How long reports are counted before the report rate is updated.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// `HistogramSnapshot` is a copy of a latency histogram.
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct HistogramSnapshot {
+    /// Upper bound in seconds and the number of observations at or below it, like Prometheus `le` buckets.
+    pub buckets: Vec<(f64, u64)>,
+    /// Number of observations.
+    pub count: u64,
+    /// Sum of all observations in seconds.
+    pub sum: f64,
+}
+
+impl HistogramSnapshot {
+    /// Returns the average observation in seconds, or `0` without observations.
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+}
+
+/// `MetricsSnapshot` is a copy of the controller metrics at one point in time.
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct MetricsSnapshot {
+    /// Input reports read from the device.
+    pub reports_read: u64,
+    /// Input reports read per second, measured over the last second.
+    pub reports_per_second: f64,
+    /// Reads that failed. The read thread retries with a growing delay and stops after ten
+    /// failures in a row.
+    pub read_errors: u64,
+    /// Times the device was opened again after the first [`open`](crate::Beolyd5Controller::open),
+    /// plus the times the transport reconnected by itself, see
+    /// [`Transport::reconnects`](crate::transport::Transport::reconnects).
+    pub reconnects: u64,
+    /// Writes waiting for or holding the device right now.
+    pub write_queue_depth: u64,
+    /// Time spent delivering the events of one report to the callbacks.
+    pub dispatch_duration: HistogramSnapshot,
+    /// Time from [`send`](crate::Beolyd5Controller::send) until the device accepted the report.
+    pub write_duration: HistogramSnapshot,
+}
+
+impl MetricsSnapshot {
+    /// Formats the metrics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        counter(
+            &mut out,
+            "beolyd5_reports_read_total",
+            "Input reports read from the device.",
+            self.reports_read,
+        );
+        gauge(
+            &mut out,
+            "beolyd5_reports_per_second",
+            "Input reports read per second.",
+            self.reports_per_second,
+        );
+        counter(
+            &mut out,
+            "beolyd5_read_errors_total",
+            "Failed reads from the device.",
+            self.read_errors,
+        );
+        counter(
+            &mut out,
+            "beolyd5_reconnects_total",
+            "Times the device was opened again or its transport reconnected.",
+            self.reconnects,
+        );
+        gauge(
+            &mut out,
+            "beolyd5_write_queue_depth",
+            "Writes waiting for the device.",
+            self.write_queue_depth as f64,
+        );
+        histogram(
+            &mut out,
+            "beolyd5_dispatch_duration_seconds",
+            "Time spent delivering the events of one report to the callbacks.",
+            &self.dispatch_duration,
+        );
+        histogram(
+            &mut out,
+            "beolyd5_write_duration_seconds",
+            "Time until the device accepted an output report.",
+            &self.write_duration,
+        );
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(
+        out,
+        "# HELP {} {}\n# TYPE {} counter\n{} {}",
+        name, help, name, name, value
+    );
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    let _ = writeln!(
+        out,
+        "# HELP {} {}\n# TYPE {} gauge\n{} {}",
+        name, help, name, name, value
+    );
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, histogram: &HistogramSnapshot) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name);
+    for (le, count) in &histogram.buckets {
+        let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, count);
+    }
+    let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, histogram.count);
+    let _ = writeln!(out, "{}_sum {}", name, histogram.sum);
+    let _ = writeln!(out, "{}_count {}", name, histogram.count);
+}
+
+/// `Histogram` counts durations into the [`LATENCY_BUCKETS`].
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|le| seconds <= *le) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = LATENCY_BUCKETS
+            .iter()
+            .zip(&self.buckets)
+            .map(|(le, count)| {
+                cumulative += count.load(Ordering::Relaxed);
+                (*le, cumulative)
+            })
+            .collect();
+
+        HistogramSnapshot {
+            buckets,
+            count: self.count.load(Ordering::Relaxed),
+            sum: self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9,
+        }
+    }
+}
+
+/// Reports counted in the current window, and the rate of the last complete one.
+#[derive(Debug)]
+struct RateWindow {
+    start: Instant,
+    count: u64,
+    rate: f64,
+}
+
+impl RateWindow {
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed >= RATE_WINDOW {
+            self.rate = self.count as f64 / elapsed.as_secs_f64();
+            self.start = now;
+            self.count = 0;
+        }
+    }
+}
+
+/// `Metrics` is updated by the controller as reports and writes pass through.
+#[derive(Debug)]
+pub(crate) struct Metrics {
+    reports_read: AtomicU64,
+    read_errors: AtomicU64,
+    reconnects: AtomicU64,
+    transport_reconnects: AtomicU64,
+    write_queue_depth: AtomicU64,
+    dispatch_duration: Histogram,
+    write_duration: Histogram,
+    rate: Mutex<RateWindow>,
+    opened: AtomicBool,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Metrics {
+        Metrics {
+            reports_read: AtomicU64::new(0),
+            read_errors: AtomicU64::new(0),
+            reconnects: AtomicU64::new(0),
+            transport_reconnects: AtomicU64::new(0),
+            write_queue_depth: AtomicU64::new(0),
+            dispatch_duration: Histogram::default(),
+            write_duration: Histogram::default(),
+            rate: Mutex::new(RateWindow {
+                start: Instant::now(),
+                count: 0,
+                rate: 0.0,
+            }),
+            opened: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn report_read(&self, now: Instant) {
+        self.reports_read.fetch_add(1, Ordering::Relaxed);
+        let mut rate = self.rate.lock().unwrap_or_else(PoisonError::into_inner);
+        rate.roll(now);
+        rate.count += 1;
+    }
+
+    pub(crate) fn read_failed(&self) {
+        self.read_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records an `open`, counting every open after the first as a reconnect.
+    pub(crate) fn opened(&self) {
+        if self.opened.swap(true, Ordering::Relaxed) {
+            self.reconnects.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records the reconnect count reported by the transport.
+    pub(crate) fn transport_reconnects(&self, count: u64) {
+        self.transport_reconnects.store(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dispatched(&self, duration: Duration) {
+        self.dispatch_duration.observe(duration);
+    }
+
+    pub(crate) fn write_queued(&self) {
+        self.write_queue_depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn write_done(&self, duration: Duration) {
+        self.write_queue_depth.fetch_sub(1, Ordering::Relaxed);
+        self.write_duration.observe(duration);
+    }
+
+    pub(crate) fn snapshot(&self, now: Instant) -> MetricsSnapshot {
+        let reports_per_second = {
+            let mut rate = self.rate.lock().unwrap_or_else(PoisonError::into_inner);
+            rate.roll(now);
+            rate.rate
+        };
+
+        MetricsSnapshot {
+            reports_read: self.reports_read.load(Ordering::Relaxed),
+            reports_per_second,
+            read_errors: self.read_errors.load(Ordering::Relaxed),
+            reconnects: self.reconnects.load(Ordering::Relaxed)
+                + self.transport_reconnects.load(Ordering::Relaxed),
+            write_queue_depth: self.write_queue_depth.load(Ordering::Relaxed),
+            dispatch_duration: self.dispatch_duration.snapshot(),
+            write_duration: self.write_duration.snapshot(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(50));
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_secs(2));
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 3);
+        assert_eq!(snapshot.buckets[0], (0.0001, 1));
+        assert_eq!(snapshot.buckets[5], (0.005, 2));
+        assert_eq!(snapshot.buckets.last(), Some(&(1.0, 2)));
+        assert!((snapshot.sum - 2.00305).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rate_covers_the_last_window() {
+        let metrics = Metrics::new();
+        let start = metrics.rate.lock().unwrap().start;
+        for i in 0..50 {
+            metrics.report_read(start + Duration::from_millis(i * 20));
+        }
+
+        assert_eq!(
+            metrics
+                .snapshot(start + Duration::from_millis(500))
+                .reports_per_second,
+            0.0
+        );
+        assert_eq!(
+            metrics
+                .snapshot(start + Duration::from_secs(1))
+                .reports_per_second,
+            50.0
+        );
+        assert_eq!(
+            metrics
+                .snapshot(start + Duration::from_secs(3))
+                .reports_per_second,
+            0.0
+        );
+    }
+
+    #[test]
+    fn exports_prometheus_text() {
+        let metrics = Metrics::new();
+        metrics.opened();
+        metrics.opened();
+        metrics.transport_reconnects(2);
+        metrics.write_queued();
+        metrics.write_done(Duration::from_millis(2));
+
+        let text = metrics.snapshot(Instant::now()).to_prometheus();
+        assert!(
+            text.contains("# TYPE beolyd5_reconnects_total counter\nbeolyd5_reconnects_total 3\n")
+        );
+        assert!(text.contains("beolyd5_write_queue_depth 0\n"));
+        assert!(text.contains("beolyd5_write_duration_seconds_bucket{le=\"0.001\"} 0\n"));
+        assert!(text.contains("beolyd5_write_duration_seconds_bucket{le=\"0.0025\"} 1\n"));
+        assert!(text.contains("beolyd5_write_duration_seconds_count 1\n"));
+    }
+}
@@ -0,0 +1,347 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Per-panel tuning, persisted in a TOML file.
+//!
+//! Every panel feels a little different, so [`ControllerSettings`] are stored per serial number in
+//! a [`SettingsFile`]:
+//!
+//! ```toml
+//! [default]
+//! click_on_press = true
+//!
+//! [devices.ABC123.front_wheel]
+//! sensitivity = 2.0
+//! inverted = true
+//! ```
+//!
+//! A device entry only needs the keys that differ from `[default]`. The controller loads the
+//! settings of its panel when it is opened, see
+//! [`Beolyd5Controller::set_settings_path`](crate::Beolyd5Controller::set_settings_path).
+//! Device callbacks always receive the raw reports; wheel callbacks receive the adjusted movement.
+
+use crate::accumulator::relative_movement;
+use crate::decoder::ANGULAR_MAX;
+use crate::types::{Event, Wheel};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// `WheelSettings` tunes the front or back wheel.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct WheelSettings {
+    /// Factor applied to every movement. Fractions of a step are carried over to the next movement.
+    pub sensitivity: f64,
+    /// Swap clockwise and counter-clockwise.
+    pub inverted: bool,
+    /// Extra factor for fast movement: a report of `n` steps is scaled by `1 + acceleration * (n - 1)`.
+    pub acceleration: f64,
+}
+
+impl Default for WheelSettings {
+    fn default() -> Self {
+        WheelSettings {
+            sensitivity: 1.0,
+            inverted: false,
+            acceleration: 0.0,
+        }
+    }
+}
+
+/// `AngularSettings` calibrates the angular wheel.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AngularSettings {
+    /// Raw positions this panel reports at the ends of the arc. They are mapped to `0..=ANGULAR_MAX`.
+    pub min: u8,
+    pub max: u8,
+    /// Report `ANGULAR_MAX` at the top instead of `0`.
+    pub inverted: bool,
+}
+
+impl Default for AngularSettings {
+    fn default() -> Self {
+        AngularSettings {
+            min: 0,
+            max: ANGULAR_MAX,
+            inverted: false,
+        }
+    }
+}
+
+/// `ControllerSettings` is the tuning of one panel.
+#[derive(Debug, Copy, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ControllerSettings {
+    pub front_wheel: WheelSettings,
+    pub back_wheel: WheelSettings,
+    pub angular_wheel: AngularSettings,
+    /// Click whenever a button is pressed.
+    pub click_on_press: bool,
+    /// Turn the backlight off after this many seconds without input, and on again at the next input.
+    pub idle_timeout_secs: Option<u64>,
+}
+
+/// `SettingsFile` holds the settings of every known panel.
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SettingsFile {
+    /// Settings for panels without a serial number or without settings of their own.
+    pub default: ControllerSettings,
+    /// Settings by serial number, complete with the defaults they did not set.
+    pub devices: BTreeMap<String, ControllerSettings>,
+}
+
+impl SettingsFile {
+    /// Reads the settings file at `path`. A missing file yields the defaults.
+    pub fn load(path: &Path) -> Result<SettingsFile, Box<dyn Error>> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(SettingsFile::parse(&text)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(SettingsFile::default()),
+            Err(err) => Err(Box::new(err)),
+        }
+    }
+
+    /// Parses a settings file, filling in the keys a device entry leaves out from `[default]`.
+    pub fn parse(text: &str) -> Result<SettingsFile, toml::de::Error> {
+        let mut table: toml::Table = toml::from_str(text)?;
+        let default = match table.get("default") {
+            Some(toml::Value::Table(default)) => default.clone(),
+            _ => toml::Table::new(),
+        };
+        if let Some(toml::Value::Table(devices)) = table.get_mut("devices") {
+            for (_, device) in devices.iter_mut() {
+                if let toml::Value::Table(entry) = device {
+                    let mut layered = default.clone();
+                    layer(&mut layered, entry);
+                    *entry = layered;
+                }
+            }
+        }
+        table.try_into()
+    }
+
+    /// Writes the settings file to `path`, creating its directory if needed.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Returns the settings of the panel with `serial_number`, falling back to the defaults.
+    pub fn get(&self, serial_number: Option<&str>) -> ControllerSettings {
+        serial_number
+            .and_then(|serial| self.devices.get(serial))
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    /// Stores the settings of the panel with `serial_number`, or the defaults without a serial number.
+    pub fn set(&mut self, serial_number: Option<&str>, settings: ControllerSettings) {
+        match serial_number {
+            Some(serial) => {
+                self.devices.insert(serial.to_string(), settings);
+            }
+            None => self.default = settings,
+        }
+    }
+}
+
+/// Sets every key of `over` in `base`, merging tables key by key.
+fn layer(base: &mut toml::Table, over: &toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => layer(base, over),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Returns where the settings are kept by default: `beolyd5/controller.toml` in
+/// `$XDG_CONFIG_HOME`, or in `~/.config` when that is not set.
+pub fn default_path() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("beolyd5").join("controller.toml"))
+}
+
+/// `Shaping` applies [`ControllerSettings`] to decoded events, carrying fractions of wheel steps.
+#[derive(Debug, Default)]
+pub(crate) struct Shaping {
+    front: f64,
+    back: f64,
+}
+
+impl Shaping {
+    /// Returns the adjusted event, or `None` when a wheel movement was scaled below one step.
+    pub(crate) fn apply(&mut self, settings: &ControllerSettings, event: Event) -> Option<Event> {
+        match event {
+            Event::WheelMoved(Wheel::Front, pos) => {
+                scale(&settings.front_wheel, &mut self.front, pos)
+                    .map(|pos| Event::WheelMoved(Wheel::Front, pos))
+            }
+            Event::WheelMoved(Wheel::Back, pos) => scale(&settings.back_wheel, &mut self.back, pos)
+                .map(|pos| Event::WheelMoved(Wheel::Back, pos)),
+            Event::WheelMoved(Wheel::Angular, pos) => Some(Event::WheelMoved(
+                Wheel::Angular,
+                calibrate(&settings.angular_wheel, pos),
+            )),
+            _ => Some(event),
+        }
+    }
+}
+
+/// Scales a relative wheel movement, carrying fractions of a step in `remainder`.
+pub(crate) fn scale(settings: &WheelSettings, remainder: &mut f64, pos: u8) -> Option<u8> {
+    let steps = relative_movement(pos) as f64;
+    let steps = if settings.inverted { -steps } else { steps };
+    let factor = settings.sensitivity * (1.0 + settings.acceleration * (steps.abs() - 1.0));
+
+    let total = steps * factor + *remainder;
+    let whole = total.trunc();
+    *remainder = total - whole;
+    if whole == 0.0 {
+        return None;
+    }
+    Some(whole.clamp(i8::MIN as f64 + 1.0, i8::MAX as f64) as i8 as u8)
+}
+
+fn calibrate(settings: &AngularSettings, pos: u8) -> u8 {
+    if settings.max <= settings.min {
+        return pos;
+    }
+
+    let offset = (pos.clamp(settings.min, settings.max) - settings.min) as f64;
+    let scaled = (offset * ANGULAR_MAX as f64 / (settings.max - settings.min) as f64).round() as u8;
+    if settings.inverted {
+        ANGULAR_MAX - scaled
+    } else {
+        scaled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_leave_events_alone() {
+        let mut shaping = Shaping::default();
+        let settings = ControllerSettings::default();
+
+        for event in [
+            Event::WheelMoved(Wheel::Front, 0x01),
+            Event::WheelMoved(Wheel::Back, 0xfe),
+            Event::WheelMoved(Wheel::Angular, 0x40),
+        ] {
+            assert_eq!(shaping.apply(&settings, event), Some(event));
+        }
+    }
+
+    #[test]
+    fn scales_and_inverts_wheels() {
+        let mut shaping = Shaping::default();
+        let settings = ControllerSettings {
+            front_wheel: WheelSettings {
+                sensitivity: 0.5,
+                inverted: true,
+                ..WheelSettings::default()
+            },
+            back_wheel: WheelSettings {
+                acceleration: 1.0,
+                ..WheelSettings::default()
+            },
+            ..ControllerSettings::default()
+        };
+
+        assert_eq!(
+            shaping.apply(&settings, Event::WheelMoved(Wheel::Front, 0x01)),
+            None
+        );
+        assert_eq!(
+            shaping.apply(&settings, Event::WheelMoved(Wheel::Front, 0x01)),
+            Some(Event::WheelMoved(Wheel::Front, 0xff))
+        );
+        assert_eq!(
+            shaping.apply(&settings, Event::WheelMoved(Wheel::Back, 0x03)),
+            Some(Event::WheelMoved(Wheel::Back, 0x09))
+        );
+    }
+
+    #[test]
+    fn calibrates_the_angular_wheel() {
+        let settings = AngularSettings {
+            min: 10,
+            max: 70,
+            inverted: true,
+        };
+
+        assert_eq!(calibrate(&settings, 0), ANGULAR_MAX);
+        assert_eq!(calibrate(&settings, 40), 60);
+        assert_eq!(calibrate(&settings, 90), 0);
+    }
+
+    #[test]
+    fn settings_file_round_trip() {
+        let mut file = SettingsFile::parse(
+            r#"
+            [default]
+            click_on_press = true
+
+            [devices.ABC123]
+            click_on_press = false
+            "#,
+        )
+        .unwrap();
+
+        assert!(file.get(None).click_on_press);
+        assert!(file.get(Some("unknown")).click_on_press);
+        let panel = file.get(Some("ABC123"));
+        assert!(!panel.click_on_press);
+
+        file.set(
+            Some("XYZ"),
+            ControllerSettings {
+                idle_timeout_secs: Some(60),
+                ..panel
+            },
+        );
+        let text = toml::to_string_pretty(&file).unwrap();
+        assert_eq!(SettingsFile::parse(&text).unwrap(), file);
+    }
+
+    #[test]
+    fn device_entries_layer_over_the_defaults() {
+        let file = SettingsFile::parse(
+            r#"
+            [default]
+            click_on_press = true
+            idle_timeout_secs = 60
+
+            [default.front_wheel]
+            sensitivity = 0.5
+            inverted = true
+
+            [devices.ABC123.front_wheel]
+            sensitivity = 2.0
+            "#,
+        )
+        .unwrap();
+
+        let panel = file.get(Some("ABC123"));
+        assert_eq!(panel.front_wheel.sensitivity, 2.0);
+        assert!(panel.front_wheel.inverted);
+        assert!(panel.click_on_press);
+        assert_eq!(panel.idle_timeout_secs, Some(60));
+    }
+}
@@ -0,0 +1,318 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Linux transport for panels claimed by the out-of-tree
+//! [beosound5 kernel module](https://github.com/Frankkkkk/beosound5-kernel-module).
+//!
+//! The module turns the panel into an evdev input device, so the raw HID device can no longer be
+//! opened. This transport reads the module's `input_event`s from `/dev/input/event*` and rebuilds
+//! the 6-byte input report from them, so the rest of the crate produces the usual `Wheel` and
+//! `Button` events no matter which driver is in charge.
+//!
+//! The kernel module does not expose the panel outputs, so writing output and feature reports
+//! fails with `ErrorKind::Unsupported`. The report descriptor is read from sysfs.
+
+use super::Transport;
+use crate::decoder::{
+    ANGULAR_WHEEL_BYTE, BACK_WHEEL_BYTE, BUTTON_BYTE, FRONT_WHEEL_BYTE, REPORT_LEN,
+};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+
+/// Where the kernel lists input event devices.
+pub const SYSFS_INPUT: &str = "/sys/class/input";
+
+/// Event types from `linux/input-event-codes.h`.
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const EV_ABS: u16 = 0x03;
+pub const SYN_REPORT: u16 = 0x00;
+
+/// `EvdevMapping` tells which evdev codes the kernel module uses for the panel controls.
+///
+/// The defaults are an assumption, not taken from a capture of the panel's evdev node: they are
+/// the generic codes a mouse-like driver would report. Check them against `evtest` output for
+/// your module version and override them with [`EvdevTransport::open_path`] if they differ.
+/// Wheels are `(event type, code)` pairs, so a wheel can be remapped between relative and
+/// absolute axes.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EvdevMapping {
+    pub front_wheel: (u16, u16),
+    pub back_wheel: (u16, u16),
+    pub angular_wheel: (u16, u16),
+    pub left: u16,
+    pub right: u16,
+    pub go: u16,
+    pub standby: u16,
+}
+
+impl Default for EvdevMapping {
+    fn default() -> Self {
+        EvdevMapping {
+            front_wheel: (EV_REL, 0x08),   // REL_WHEEL
+            back_wheel: (EV_REL, 0x06),    // REL_HWHEEL
+            angular_wheel: (EV_ABS, 0x00), // ABS_X
+            left: 0x110,                   // BTN_LEFT
+            right: 0x111,                  // BTN_RIGHT
+            go: 0x112,                     // BTN_MIDDLE
+            standby: 0x74,                 // KEY_POWER
+        }
+    }
+}
+
+/// `ReportBuilder` rebuilds input reports from a stream of evdev events.
+#[derive(Debug, Clone, Default)]
+pub struct ReportBuilder {
+    mapping: EvdevMapping,
+    report: [u8; REPORT_LEN],
+    dirty: bool,
+}
+
+impl ReportBuilder {
+    /// Creates a builder for the given mapping, starting from an idle report.
+    pub fn new(mapping: EvdevMapping) -> ReportBuilder {
+        ReportBuilder {
+            mapping,
+            report: [0u8; REPORT_LEN],
+            dirty: false,
+        }
+    }
+
+    /// Feeds a single evdev event. Returns a report when a `SYN_REPORT` completes a change.
+    pub fn handle(&mut self, type_: u16, code: u16, value: i32) -> Option<[u8; REPORT_LEN]> {
+        let m = self.mapping;
+        match (type_, code) {
+            (EV_SYN, SYN_REPORT) => {
+                if !self.dirty {
+                    return None;
+                }
+                let report = self.report;
+                // Front and back wheels are relative, they are idle again after every report
+                self.report[FRONT_WHEEL_BYTE] = 0;
+                self.report[BACK_WHEEL_BYTE] = 0;
+                self.dirty = false;
+                return Some(report);
+            }
+            t if t == m.front_wheel => self.report[FRONT_WHEEL_BYTE] = relative(value),
+            t if t == m.back_wheel => self.report[BACK_WHEEL_BYTE] = relative(value),
+            t if t == m.angular_wheel => {
+                self.report[ANGULAR_WHEEL_BYTE] = value.clamp(0, 0xff) as u8
+            }
+            (EV_KEY, key) => {
+                let bit = match key {
+                    k if k == m.left => 0x20,
+                    k if k == m.right => 0x10,
+                    k if k == m.go => 0x40,
+                    k if k == m.standby => 0x80,
+                    _ => return None,
+                };
+                // Value 2 is key repeat, which does not change the button state
+                match value {
+                    0 => self.report[BUTTON_BYTE] &= !bit,
+                    1 => self.report[BUTTON_BYTE] |= bit,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        }
+
+        self.dirty = true;
+        None
+    }
+}
+
+fn relative(value: i32) -> u8 {
+    value.clamp(i8::MIN as i32, i8::MAX as i32) as i8 as u8
+}
+
+/// `EvdevTransport` reads the panel through the input device created by the kernel module.
+pub struct EvdevTransport {
+    file: File,
+    path: PathBuf,
+    builder: ReportBuilder,
+    pending: VecDeque<[u8; REPORT_LEN]>,
+    serial_number: Option<String>,
+}
+
+/// `EvdevDevice` is an input event device found in sysfs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvdevDevice {
+    /// Device node, e.g. `/dev/input/event3`.
+    pub path: PathBuf,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub name: String,
+    /// The `uniq` reported by the kernel, usually the USB serial number.
+    pub serial_number: String,
+}
+
+impl EvdevTransport {
+    /// Opens the first input event device with the given USB IDs, using the default mapping.
+    pub fn open(vendor_id: u16, product_id: u16) -> io::Result<EvdevTransport> {
+        let device = list_devices(Path::new(SYSFS_INPUT))?
+            .into_iter()
+            .find(|d| d.vendor_id == vendor_id && d.product_id == product_id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "BS5 controller not found"))?;
+
+        Self::open_path(&device.path, EvdevMapping::default())
+    }
+
+    /// Opens a specific input event device node with the given mapping.
+    pub fn open_path(path: &Path, mapping: EvdevMapping) -> io::Result<EvdevTransport> {
+        let file = File::open(path)?;
+        let serial_number = list_devices(Path::new(SYSFS_INPUT))
+            .unwrap_or_default()
+            .into_iter()
+            .find(|d| d.path == path)
+            .map(|d| d.serial_number)
+            .filter(|s| !s.is_empty());
+
+        Ok(EvdevTransport {
+            file,
+            path: path.to_path_buf(),
+            builder: ReportBuilder::new(mapping),
+            pending: VecDeque::new(),
+            serial_number,
+        })
+    }
+
+    /// Returns the device node this transport is using.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Transport for EvdevTransport {
+    fn read_timeout(&mut self, buf: &mut [u8], timeout_ms: i32) -> io::Result<usize> {
+        if self.pending.is_empty() && super::poll_readable(&self.file, timeout_ms)? {
+            let event_size = size_of::<libc::input_event>();
+            let mut raw = vec![0u8; event_size * 64];
+            let len = self.file.read(&mut raw)?;
+
+            for chunk in raw[..len - len % event_size].chunks_exact(event_size) {
+                // SAFETY: the chunk holds exactly one `input_event` as written by the kernel.
+                let event: libc::input_event =
+                    unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const libc::input_event) };
+                if let Some(report) = self.builder.handle(event.type_, event.code, event.value) {
+                    self.pending.push_back(report);
+                }
+            }
+        }
+
+        match self.pending.pop_front() {
+            Some(report) => {
+                let len = report.len().min(buf.len());
+                buf[..len].copy_from_slice(&report[..len]);
+                Ok(len)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn write(&mut self, _data: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the beosound5 kernel module does not expose the panel outputs",
+        ))
+    }
+
+    fn serial_number(&self) -> Option<String> {
+        self.serial_number.clone()
+    }
+
+    fn report_descriptor(&self) -> io::Result<Vec<u8>> {
+        // eventN/device is the input device, its parent the HID device
+        let name = self
+            .path
+            .file_name()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        fs::read(
+            Path::new(SYSFS_INPUT)
+                .join(name)
+                .join("device")
+                .join("device")
+                .join("report_descriptor"),
+        )
+    }
+}
+
+/// Lists all input event devices below `sysfs_root` (normally [`SYSFS_INPUT`]).
+pub fn list_devices(sysfs_root: &Path) -> io::Result<Vec<EvdevDevice>> {
+    let mut devices = Vec::new();
+    for entry in fs::read_dir(sysfs_root)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        if !file_name.to_string_lossy().starts_with("event") {
+            continue;
+        }
+
+        let device = entry.path().join("device");
+        let read_id = |name: &str| -> Option<u16> {
+            let value = fs::read_to_string(device.join("id").join(name)).ok()?;
+            u16::from_str_radix(value.trim(), 16).ok()
+        };
+        let (Some(vendor_id), Some(product_id)) = (read_id("vendor"), read_id("product")) else {
+            continue;
+        };
+        let name = fs::read_to_string(device.join("name")).unwrap_or_default();
+        let serial_number = fs::read_to_string(device.join("uniq")).unwrap_or_default();
+
+        devices.push(EvdevDevice {
+            path: Path::new("/dev/input").join(&file_name),
+            vendor_id,
+            product_id,
+            name: name.trim().to_string(),
+            serial_number: serial_number.trim().to_string(),
+        });
+    }
+    devices.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(devices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_reports_on_syn() {
+        let m = EvdevMapping::default();
+        let mut builder = ReportBuilder::new(m);
+
+        assert_eq!(builder.handle(m.front_wheel.0, m.front_wheel.1, -1), None);
+        assert_eq!(
+            builder.handle(m.angular_wheel.0, m.angular_wheel.1, 0x40),
+            None
+        );
+        assert_eq!(
+            builder.handle(EV_SYN, SYN_REPORT, 0),
+            Some([0xff, 0, 0x40, 0, 0, 0])
+        );
+
+        // Relative wheels reset, the angular position and buttons stick
+        assert_eq!(builder.handle(EV_KEY, m.go, 1), None);
+        assert_eq!(
+            builder.handle(EV_SYN, SYN_REPORT, 0),
+            Some([0, 0, 0x40, 0x40, 0, 0])
+        );
+        assert_eq!(builder.handle(EV_KEY, m.go, 0), None);
+        assert_eq!(
+            builder.handle(EV_SYN, SYN_REPORT, 0),
+            Some([0, 0, 0x40, 0, 0, 0])
+        );
+    }
+
+    #[test]
+    fn ignores_unmapped_events_and_empty_syn() {
+        let mut builder = ReportBuilder::new(EvdevMapping::default());
+
+        assert_eq!(builder.handle(EV_KEY, 0x1, 1), None);
+        assert_eq!(builder.handle(EV_SYN, SYN_REPORT, 0), None);
+    }
+}
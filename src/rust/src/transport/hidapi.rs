@@ -0,0 +1,69 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+use super::Transport;
+use hidapi::{HidApi, HidDevice};
+use std::error::Error;
+use std::io;
+
+/// `HidApiTransport` talks to the panel through the `hidapi` C library.
+pub struct HidApiTransport {
+    device: HidDevice,
+}
+
+impl HidApiTransport {
+    /// Opens the first HID device with the given USB IDs.
+    pub fn open(vendor_id: u16, product_id: u16) -> Result<HidApiTransport, Box<dyn Error>> {
+        let api = HidApi::new()?;
+        let device = match api.open(vendor_id, product_id) {
+            Ok(device) => device,
+            Err(_) => return Err(Box::new(io::Error::other("BS5 controller not found"))),
+        };
+
+        Ok(HidApiTransport { device })
+    }
+}
+
+impl Transport for HidApiTransport {
+    fn read_timeout(&mut self, buf: &mut [u8], timeout_ms: i32) -> io::Result<usize> {
+        self.device
+            .read_timeout(buf, timeout_ms)
+            .map_err(io::Error::other)
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.device.write(data).map_err(io::Error::other)
+    }
+
+    fn serial_number(&self) -> Option<String> {
+        self.device
+            .get_serial_number_string()
+            .ok()
+            .flatten()
+            .filter(|s| !s.is_empty())
+    }
+
+    fn report_descriptor(&self) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; hidapi::MAX_REPORT_DESCRIPTOR_SIZE];
+        let len = self
+            .device
+            .get_report_descriptor(&mut buf)
+            .map_err(io::Error::other)?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    fn get_feature_report(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.device
+            .get_feature_report(buf)
+            .map_err(io::Error::other)
+    }
+
+    fn send_feature_report(&mut self, data: &[u8]) -> io::Result<()> {
+        self.device
+            .send_feature_report(data)
+            .map_err(io::Error::other)
+    }
+}
@@ -0,0 +1,203 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Pure-Rust Linux transport using the kernel's `/dev/hidraw*` devices.
+//!
+//! The device is located through sysfs (`/sys/class/hidraw/*/device/uevent`) by its USB IDs, and
+//! reports are exchanged with plain reads and writes on the device node. Feature reports use the
+//! hidraw ioctls.
+
+use super::Transport;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// Where the kernel lists hidraw devices.
+pub const SYSFS_HIDRAW: &str = "/sys/class/hidraw";
+
+/// `HidrawTransport` talks to the panel through a `/dev/hidraw*` device node.
+pub struct HidrawTransport {
+    file: File,
+    path: PathBuf,
+    serial_number: Option<String>,
+}
+
+/// `HidrawDevice` is a hidraw device found in sysfs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HidrawDevice {
+    /// Device node, e.g. `/dev/hidraw0`.
+    pub path: PathBuf,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// The `HID_NAME` reported by the kernel.
+    pub name: String,
+    /// The `HID_UNIQ` reported by the kernel, usually the USB serial number.
+    pub serial_number: String,
+}
+
+impl HidrawTransport {
+    /// Opens the first hidraw device with the given USB IDs.
+    pub fn open(vendor_id: u16, product_id: u16) -> io::Result<HidrawTransport> {
+        let device = list_devices(Path::new(SYSFS_HIDRAW))?
+            .into_iter()
+            .find(|d| d.vendor_id == vendor_id && d.product_id == product_id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "BS5 controller not found"))?;
+
+        Self::open_path(&device.path)
+    }
+
+    /// Opens a specific hidraw device node.
+    pub fn open_path(path: &Path) -> io::Result<HidrawTransport> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let serial_number = list_devices(Path::new(SYSFS_HIDRAW))
+            .unwrap_or_default()
+            .into_iter()
+            .find(|d| d.path == path)
+            .map(|d| d.serial_number)
+            .filter(|s| !s.is_empty());
+
+        Ok(HidrawTransport {
+            file,
+            path: path.to_path_buf(),
+            serial_number,
+        })
+    }
+
+    /// Returns the device node this transport is using.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Transport for HidrawTransport {
+    fn read_timeout(&mut self, buf: &mut [u8], timeout_ms: i32) -> io::Result<usize> {
+        if !super::poll_readable(&self.file, timeout_ms)? {
+            return Ok(0);
+        }
+
+        self.file.read(buf)
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.file.write(data)
+    }
+
+    fn serial_number(&self) -> Option<String> {
+        self.serial_number.clone()
+    }
+
+    fn report_descriptor(&self) -> io::Result<Vec<u8>> {
+        let name = self
+            .path
+            .file_name()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        fs::read(
+            Path::new(SYSFS_HIDRAW)
+                .join(name)
+                .join("device")
+                .join("report_descriptor"),
+        )
+    }
+
+    fn get_feature_report(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.feature_ioctl(HIDIOCGFEATURE_NR, buf)
+    }
+
+    fn send_feature_report(&mut self, data: &[u8]) -> io::Result<()> {
+        // The kernel only reads from the buffer for HIDIOCSFEATURE
+        let mut buf = data.to_vec();
+        self.feature_ioctl(HIDIOCSFEATURE_NR, &mut buf).map(|_| ())
+    }
+}
+
+/// Numbers of the `HIDIOCSFEATURE` and `HIDIOCGFEATURE` ioctls from `linux/hidraw.h`.
+const HIDIOCSFEATURE_NR: u8 = 0x06;
+const HIDIOCGFEATURE_NR: u8 = 0x07;
+
+impl HidrawTransport {
+    fn feature_ioctl(&self, nr: u8, buf: &mut [u8]) -> io::Result<usize> {
+        // _IOC(_IOC_WRITE | _IOC_READ, 'H', nr, len) with the generic ioctl encoding
+        let request =
+            (3u32 << 30) | ((buf.len() as u32 & 0x3fff) << 16) | ((b'H' as u32) << 8) | nr as u32;
+
+        // SAFETY: the kernel reads and writes at most `buf.len()` bytes, as encoded in the request.
+        let result = unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, buf.as_mut_ptr()) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(result as usize)
+    }
+}
+
+/// Lists all hidraw devices below `sysfs_root` (normally [`SYSFS_HIDRAW`]).
+pub fn list_devices(sysfs_root: &Path) -> io::Result<Vec<HidrawDevice>> {
+    let mut devices = Vec::new();
+    for entry in fs::read_dir(sysfs_root)? {
+        let entry = entry?;
+        let uevent = match fs::read_to_string(entry.path().join("device").join("uevent")) {
+            Ok(uevent) => uevent,
+            Err(_) => continue,
+        };
+        if let Some(mut device) = parse_uevent(&uevent) {
+            device.path = Path::new("/dev").join(entry.file_name());
+            devices.push(device);
+        }
+    }
+    devices.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(devices)
+}
+
+/// Parses the `uevent` file of a HID device, e.g. `HID_ID=0003:00000CD4:00001112`.
+fn parse_uevent(uevent: &str) -> Option<HidrawDevice> {
+    let mut ids = None;
+    let mut name = String::new();
+    let mut serial_number = String::new();
+
+    for line in uevent.lines() {
+        match line.split_once('=') {
+            Some(("HID_ID", value)) => {
+                let mut parts = value.split(':').skip(1);
+                let vendor = u32::from_str_radix(parts.next()?, 16).ok()?;
+                let product = u32::from_str_radix(parts.next()?, 16).ok()?;
+                ids = Some((vendor as u16, product as u16));
+            }
+            Some(("HID_NAME", value)) => name = value.to_string(),
+            Some(("HID_UNIQ", value)) => serial_number = value.to_string(),
+            _ => (),
+        }
+    }
+
+    let (vendor_id, product_id) = ids?;
+    Some(HidrawDevice {
+        path: PathBuf::new(),
+        vendor_id,
+        product_id,
+        name,
+        serial_number,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_uevent() {
+        let uevent = "DRIVER=hid-generic\nHID_ID=0003:00000CD4:00001112\nHID_NAME=Bang & Olufsen BeoSound 5\nHID_PHYS=usb-0000:01:00.0-1.3/input0\nHID_UNIQ=ABC123\nMODALIAS=hid:b0003g0001v00000CD4p00001112\n";
+        let device = parse_uevent(uevent).unwrap();
+
+        assert_eq!(device.vendor_id, 0x0cd4);
+        assert_eq!(device.product_id, 0x1112);
+        assert_eq!(device.name, "Bang & Olufsen BeoSound 5");
+        assert_eq!(device.serial_number, "ABC123");
+    }
+
+    #[test]
+    fn ignores_uevent_without_ids() {
+        assert_eq!(parse_uevent("DRIVER=hid-generic\n"), None);
+    }
+}
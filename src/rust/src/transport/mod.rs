@@ -0,0 +1,141 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Transports move raw reports between [`Beolyd5Controller`](crate::Beolyd5Controller) and a panel.
+//!
+//! Which transports are available depends on the enabled cargo features:
+//! - `hidapi` (default): any platform supported by the `hidapi` crate
+//! - `hidraw`: Linux `/dev/hidraw*` in pure Rust, without any C dependencies
+//! - `evdev`: Linux `/dev/input/event*` for panels claimed by the beosound5 kernel module
+//!
+//! [`remote`] is always available and reaches a panel attached to another machine over TCP.
+
+use std::error::Error;
+use std::io;
+
+#[cfg(all(feature = "evdev", target_os = "linux"))]
+pub mod evdev;
+#[cfg(feature = "hidapi")]
+pub mod hidapi;
+#[cfg(all(feature = "hidraw", target_os = "linux"))]
+pub mod hidraw;
+pub mod remote;
+
+/// `Transport` reads input reports from and writes output reports to a BeoSound 5 panel.
+///
+/// Reports use the same layout as `hidapi`: output reports start with the report ID, which is
+/// `0x00` for the panel.
+pub trait Transport: Send {
+    /// Reads an input report into `buf`, waiting at most `timeout_ms` milliseconds (`-1` blocks).
+    /// Returns the number of bytes read, or `0` if the timeout expired.
+    fn read_timeout(&mut self, buf: &mut [u8], timeout_ms: i32) -> io::Result<usize>;
+
+    /// Writes an output report, returning the number of bytes written.
+    fn write(&mut self, data: &[u8]) -> io::Result<usize>;
+
+    /// Returns the serial number of the panel, if the transport knows it.
+    fn serial_number(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the raw HID report descriptor of the panel, see [`crate::descriptor`].
+    fn report_descriptor(&self) -> io::Result<Vec<u8>> {
+        Err(unsupported("reading the report descriptor"))
+    }
+
+    /// Reads a feature report into `buf`, whose first byte selects the report ID (`0x00` for
+    /// unnumbered reports). Returns the number of bytes read, including the report ID.
+    fn get_feature_report(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(unsupported("feature reports"))
+    }
+
+    /// Writes a feature report. The first byte of `data` is the report ID, as for output reports.
+    fn send_feature_report(&mut self, _data: &[u8]) -> io::Result<()> {
+        Err(unsupported("feature reports"))
+    }
+
+    /// Returns how often the transport has re-established its connection to the panel by itself.
+    fn reconnects(&self) -> u64 {
+        0
+    }
+}
+
+fn unsupported(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("this transport does not support {}", what),
+    )
+}
+
+/// Opens the panel with the given USB IDs using the first transport enabled by cargo features.
+/// `hidraw` is preferred over `hidapi` when both are enabled, and `evdev` is tried last since the
+/// kernel module only claims the panel when the raw HID device is not available.
+pub fn open_default(vendor_id: u16, product_id: u16) -> Result<Box<dyn Transport>, Box<dyn Error>> {
+    #[allow(unused_mut)]
+    let mut errors: Vec<String> = Vec::new();
+
+    #[cfg(all(feature = "hidraw", target_os = "linux"))]
+    match hidraw::HidrawTransport::open(vendor_id, product_id) {
+        Ok(transport) => return Ok(Box::new(transport)),
+        Err(err) => errors.push(format!("hidraw: {}", err)),
+    }
+
+    #[cfg(feature = "hidapi")]
+    match hidapi::HidApiTransport::open(vendor_id, product_id) {
+        Ok(transport) => return Ok(Box::new(transport)),
+        Err(err) => errors.push(format!("hidapi: {}", err)),
+    }
+
+    #[cfg(all(feature = "evdev", target_os = "linux"))]
+    match evdev::EvdevTransport::open(vendor_id, product_id) {
+        Ok(transport) => return Ok(Box::new(transport)),
+        Err(err) => errors.push(format!("evdev: {}", err)),
+    }
+
+    if errors.is_empty() {
+        let _ = (vendor_id, product_id);
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no transport enabled, enable the `hidapi`, `hidraw` or `evdev` feature",
+        )));
+    }
+
+    Err(Box::new(io::Error::new(
+        io::ErrorKind::NotFound,
+        errors.join(", "),
+    )))
+}
+
+/// Waits until `file` is readable or `timeout_ms` milliseconds passed (`-1` blocks).
+/// Returns `false` on timeout.
+#[cfg(all(any(feature = "hidraw", feature = "evdev"), target_os = "linux"))]
+pub(crate) fn poll_readable(file: &std::fs::File, timeout_ms: i32) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut fds = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    // SAFETY: `fds` is a valid pollfd for the duration of the call and we pass a count of 1.
+    let ready = unsafe { libc::poll(&mut fds, 1, timeout_ms) };
+    if ready < 0 {
+        let err = io::Error::last_os_error();
+        return if err.kind() == io::ErrorKind::Interrupted {
+            Ok(false)
+        } else {
+            Err(err)
+        };
+    }
+    if ready > 0 && fds.revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::BrokenPipe,
+            "BS5 controller disconnected",
+        ));
+    }
+
+    Ok(ready > 0)
+}
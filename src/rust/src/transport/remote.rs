@@ -0,0 +1,584 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! A panel attached to another machine, reached over TCP.
+//!
+//! [`serve`] runs next to the panel and forwards its reports to one client at a time;
+//! [`RemoteTransport`] is that client, so the controller works as if the panel were local:
+//!
+//! ```no_run
+//! use beolyd5_controller::transport::remote::RemoteTransport;
+//! use beolyd5_controller::Beolyd5Controller;
+//!
+//! let transport = RemoteTransport::connect("livingroom-pi:9106").unwrap();
+//! let mut controller = Beolyd5Controller::with_transport(transport);
+//! controller.open().unwrap();
+//! ```
+//!
+//! Both sides exchange frames of a kind byte, a big-endian `u16` length and the data: input
+//! reports from the server, output reports from the client. On connect the server first sends a
+//! hello frame with the serial number and report descriptor of the panel, and while the panel is
+//! idle it sends heartbeat frames so the client can tell a quiet panel from a dead link.
+//!
+//! When the link drops, or nothing arrives from the server for a few heartbeats, the client keeps
+//! reporting no input while a background thread reconnects; writes fail until the link is back.
+
+use super::Transport;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Port used by the examples.
+pub const DEFAULT_PORT: u16 = 9106;
+
+/// How long to wait between attempts to reconnect.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a connect or the hello frame may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often the server sends a heartbeat while the panel is idle.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the client waits for any frame before it considers the link dead.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long the server may block writing to a client before dropping it.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+const FRAME_INPUT: u8 = 0x01;
+const FRAME_OUTPUT: u8 = 0x02;
+const FRAME_HELLO: u8 = 0x03;
+const FRAME_HEARTBEAT: u8 = 0x04;
+
+/// `RemoteTransport` talks to a panel forwarded by [`serve`] on another machine.
+pub struct RemoteTransport {
+    addrs: Vec<SocketAddr>,
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+    /// When the last data arrived from the server.
+    last_received: Instant,
+    /// Delivers the link from the reconnect thread while the link is down.
+    reconnecting: Option<Receiver<Link>>,
+    reconnects: u64,
+    closed: Arc<AtomicBool>,
+    serial_number: Option<String>,
+    descriptor: Vec<u8>,
+}
+
+/// A connection to the server after the hello frame.
+struct Link {
+    stream: TcpStream,
+    serial_number: Option<String>,
+    descriptor: Vec<u8>,
+}
+
+impl RemoteTransport {
+    /// Connects to the server at `addr`. Fails if it cannot be reached right now; once
+    /// connected, the link is re-established whenever it drops.
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<RemoteTransport> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+        let mut transport = RemoteTransport {
+            stream: None,
+            buffer: Vec::new(),
+            last_received: Instant::now(),
+            reconnecting: None,
+            reconnects: 0,
+            closed: Arc::new(AtomicBool::new(false)),
+            serial_number: None,
+            descriptor: Vec::new(),
+            addrs,
+        };
+        let link = handshake(&transport.addrs)?;
+        transport.attach(link);
+        Ok(transport)
+    }
+
+    /// Returns `true` while the link to the server is up.
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    fn attach(&mut self, link: Link) {
+        self.buffer.clear();
+        self.last_received = Instant::now();
+        self.serial_number = link.serial_number;
+        self.descriptor = link.descriptor;
+        self.stream = Some(link.stream);
+    }
+
+    /// Drops the link and starts reconnecting in the background.
+    fn disconnect(&mut self) {
+        self.stream = None;
+        if self.reconnecting.is_some() {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let addrs = self.addrs.clone();
+        let closed = self.closed.clone();
+        thread::spawn(move || {
+            while !closed.load(Ordering::Relaxed) {
+                match handshake(&addrs) {
+                    Ok(link) => {
+                        let _ = sender.send(link);
+                        return;
+                    }
+                    Err(_) => thread::sleep(RECONNECT_INTERVAL),
+                }
+            }
+        });
+        self.reconnecting = Some(receiver);
+    }
+
+    /// Takes over the link if the reconnect thread has established one. Returns `true` when
+    /// the link is up.
+    fn poll_reconnect(&mut self) -> bool {
+        if let Some(receiver) = &self.reconnecting {
+            match receiver.try_recv() {
+                Ok(link) => {
+                    self.reconnecting = None;
+                    self.reconnects += 1;
+                    self.attach(link);
+                }
+                Err(TryRecvError::Empty) => (),
+                Err(TryRecvError::Disconnected) => self.reconnecting = None,
+            }
+        }
+        self.stream.is_some()
+    }
+
+    /// Returns the next complete input report in the buffer, skipping heartbeats.
+    fn next_report(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if self.buffer.len() < 3 {
+                return Ok(None);
+            }
+            let len = u16::from_be_bytes([self.buffer[1], self.buffer[2]]) as usize;
+            if self.buffer.len() < 3 + len {
+                return Ok(None);
+            }
+
+            let frame: Vec<u8> = self.buffer.drain(..3 + len).collect();
+            match frame[0] {
+                FRAME_INPUT => return Ok(Some(frame[3..].to_vec())),
+                FRAME_HEARTBEAT => (),
+                kind => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unexpected frame kind {:#04x}", kind),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+impl Transport for RemoteTransport {
+    fn read_timeout(&mut self, buf: &mut [u8], timeout_ms: i32) -> io::Result<usize> {
+        let timeout = (timeout_ms >= 0).then(|| Duration::from_millis(timeout_ms.max(1) as u64));
+
+        if !self.poll_reconnect() {
+            thread::sleep(
+                timeout
+                    .unwrap_or(RECONNECT_INTERVAL)
+                    .min(RECONNECT_INTERVAL),
+            );
+            return Ok(0);
+        }
+
+        loop {
+            if let Some(report) = self.next_report()? {
+                let len = report.len().min(buf.len());
+                buf[..len].copy_from_slice(&report[..len]);
+                return Ok(len);
+            }
+
+            // Never wait past the point where the link is considered dead
+            let idle_left = IDLE_TIMEOUT
+                .saturating_sub(self.last_received.elapsed())
+                .max(Duration::from_millis(1));
+            let stream = self.stream.as_mut().unwrap();
+            stream.set_read_timeout(Some(timeout.map_or(idle_left, |t| t.min(idle_left))))?;
+            let mut chunk = [0u8; 256];
+            match stream.read(&mut chunk) {
+                Ok(0) => {
+                    // The server went away, keep reporting no input until it is back
+                    self.disconnect();
+                    return Ok(0);
+                }
+                Ok(n) => {
+                    self.last_received = Instant::now();
+                    self.buffer.extend_from_slice(&chunk[..n]);
+                }
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    // Not even a heartbeat, the server or the network is stuck
+                    if self.last_received.elapsed() >= IDLE_TIMEOUT {
+                        self.disconnect();
+                    }
+                    return Ok(0);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(_) => {
+                    self.disconnect();
+                    return Ok(0);
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if !self.poll_reconnect() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "the link to the server is down",
+            ));
+        }
+
+        let result = write_frame(self.stream.as_mut().unwrap(), FRAME_OUTPUT, data);
+        if result.is_err() {
+            self.disconnect();
+        }
+        result.map(|_| data.len())
+    }
+
+    fn serial_number(&self) -> Option<String> {
+        self.serial_number.clone()
+    }
+
+    fn report_descriptor(&self) -> io::Result<Vec<u8>> {
+        if self.descriptor.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the server did not send a report descriptor",
+            ));
+        }
+        Ok(self.descriptor.clone())
+    }
+
+    fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+}
+
+impl Drop for RemoteTransport {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Connects to the first of `addrs` that answers with a hello frame.
+fn handshake(addrs: &[SocketAddr]) -> io::Result<Link> {
+    let mut last_err = io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to");
+    for addr in addrs {
+        match handshake_with(addr) {
+            Ok(link) => return Ok(link),
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+fn handshake_with(addr: &SocketAddr) -> io::Result<Link> {
+    let mut stream = TcpStream::connect_timeout(addr, CONNECT_TIMEOUT)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    let (kind, hello) = read_frame(&mut stream)?;
+    if kind != FRAME_HELLO {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "expected a hello frame",
+        ));
+    }
+    let (serial_number, descriptor) = parse_hello(&hello)?;
+    Ok(Link {
+        stream,
+        serial_number,
+        descriptor,
+    })
+}
+
+/// Forwards the panel behind `transport` to clients connecting to `listener`, one at a time; a
+/// new client takes over from the previous one, and a client that stops taking frames is dropped.
+/// Runs until reading from the panel fails.
+pub fn serve(listener: TcpListener, transport: Box<dyn Transport>) -> io::Result<()> {
+    let hello = hello(
+        transport.serial_number(),
+        &transport.report_descriptor().unwrap_or_default(),
+    );
+    let transport = Arc::new(Mutex::new(transport));
+    let client: Arc<Mutex<Option<TcpStream>>> = Arc::new(Mutex::new(None));
+
+    let accept_transport = transport.clone();
+    let accept_client = client.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let Ok(reader) = stream.try_clone() else {
+                continue;
+            };
+            // Input reports only go to the client once it is stored, so the hello comes first
+            if stream.set_nodelay(true).is_err()
+                || stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err()
+                || write_frame(&mut stream, FRAME_HELLO, &hello).is_err()
+            {
+                continue;
+            }
+            if let Some(previous) = accept_client.lock().unwrap().replace(stream) {
+                let _ = previous.shutdown(std::net::Shutdown::Both);
+            }
+            let transport = accept_transport.clone();
+            thread::spawn(move || forward_output(reader, transport));
+        }
+    });
+
+    let mut buf = [0u8; 64];
+    let mut last_sent = Instant::now();
+    loop {
+        let len = transport.lock().unwrap().read_timeout(&mut buf, 100)?;
+        let frame = if len > 0 {
+            (FRAME_INPUT, &buf[..len])
+        } else if last_sent.elapsed() >= HEARTBEAT_INTERVAL {
+            (FRAME_HEARTBEAT, &[][..])
+        } else {
+            continue;
+        };
+        last_sent = Instant::now();
+
+        // Without a client the report is dropped, so nothing stale is sent on connect
+        let mut client = client.lock().unwrap();
+        if let Some(stream) = client.as_mut() {
+            // A write that times out leaves the stream in an unknown state, so the client goes
+            if write_frame(stream, frame.0, frame.1).is_err() {
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+                *client = None;
+            }
+        }
+    }
+}
+
+/// Writes the output reports sent by a client to the panel until the client goes away.
+fn forward_output(mut stream: TcpStream, transport: Arc<Mutex<Box<dyn Transport>>>) {
+    while let Ok((kind, data)) = read_frame(&mut stream) {
+        if kind != FRAME_OUTPUT {
+            break;
+        }
+        if let Err(err) = transport.lock().unwrap().write(&data) {
+            eprintln!("Failed to write output report: {:?}", err);
+        }
+    }
+}
+
+fn write_frame(stream: &mut TcpStream, kind: u8, data: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(data.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too long"))?;
+    let mut frame = Vec::with_capacity(3 + data.len());
+    frame.push(kind);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(data);
+    stream.write_all(&frame)
+}
+
+fn read_frame(stream: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 3];
+    stream.read_exact(&mut header)?;
+    let mut data = vec![0u8; u16::from_be_bytes([header[1], header[2]]) as usize];
+    stream.read_exact(&mut data)?;
+    Ok((header[0], data))
+}
+
+/// Builds the hello frame data: the length of the serial number, the serial number and the
+/// report descriptor.
+fn hello(serial_number: Option<String>, descriptor: &[u8]) -> Vec<u8> {
+    let serial_number = serial_number.unwrap_or_default();
+    let serial_number = &serial_number.as_bytes()[..serial_number.len().min(u8::MAX as usize)];
+
+    let mut data = vec![serial_number.len() as u8];
+    data.extend_from_slice(serial_number);
+    data.extend_from_slice(descriptor);
+    data
+}
+
+fn parse_hello(data: &[u8]) -> io::Result<(Option<String>, Vec<u8>)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed hello frame");
+    let len = *data.first().ok_or_else(invalid)? as usize;
+    let serial_number = data.get(1..1 + len).ok_or_else(invalid)?;
+    let serial_number = String::from_utf8_lossy(serial_number).into_owned();
+
+    Ok((
+        Some(serial_number).filter(|s| !s.is_empty()),
+        data[1 + len..].to_vec(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Led, Wheel};
+    use crate::virtual_panel::VirtualPanel;
+
+    fn read_report(transport: &mut RemoteTransport) -> Vec<u8> {
+        let mut buf = [0u8; 6];
+        for _ in 0..50 {
+            let len = transport.read_timeout(&mut buf, 100).unwrap();
+            if len > 0 {
+                return buf[..len].to_vec();
+            }
+        }
+        panic!("no report arrived");
+    }
+
+    #[test]
+    fn forwards_reports_both_ways() {
+        let panel = VirtualPanel::new();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let transport = Box::new(panel.transport().unwrap());
+        thread::spawn(move || serve(listener, transport));
+
+        let mut remote = RemoteTransport::connect(addr).unwrap();
+        assert_eq!(remote.serial_number(), None);
+        assert!(!remote.report_descriptor().unwrap().is_empty());
+
+        panel.spin(Wheel::Front, -1);
+        assert_eq!(read_report(&mut remote), vec![0xff, 0, 0, 0, 0, 0]);
+
+        remote.write(&[0x80, 0x00]).unwrap();
+        for _ in 0..50 {
+            if panel.state().output.led == Led::On {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("output report did not arrive");
+    }
+
+    #[test]
+    fn reconnects_after_link_loss() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut first, _) = listener.accept().unwrap();
+            write_frame(&mut first, FRAME_HELLO, &hello(Some("ABC123".into()), &[])).unwrap();
+            drop(first);
+
+            let (mut second, _) = listener.accept().unwrap();
+            write_frame(&mut second, FRAME_HELLO, &hello(Some("ABC123".into()), &[])).unwrap();
+            write_frame(&mut second, FRAME_INPUT, &[0, 0xff, 0, 0, 0, 0]).unwrap();
+            second
+        });
+
+        let mut remote = RemoteTransport::connect(addr).unwrap();
+        assert_eq!(remote.serial_number().as_deref(), Some("ABC123"));
+        assert_eq!(read_report(&mut remote), vec![0, 0xff, 0, 0, 0, 0]);
+        assert!(remote.is_connected());
+        assert_eq!(remote.reconnects(), 1);
+        drop(server.join().unwrap());
+    }
+
+    #[test]
+    fn writes_fail_fast_while_the_link_is_down() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            write_frame(&mut stream, FRAME_HELLO, &hello(None, &[])).unwrap();
+            // Keep the listener so reconnects are accepted but never answered
+            listener
+        });
+
+        let mut remote = RemoteTransport::connect(addr).unwrap();
+        let _listener = server.join().unwrap();
+        let mut buf = [0u8; 6];
+        while remote.is_connected() {
+            remote.read_timeout(&mut buf, 10).unwrap();
+        }
+
+        let started = Instant::now();
+        let err = remote.write(&[0x80, 0x00]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+        assert!(started.elapsed() < CONNECT_TIMEOUT);
+    }
+
+    #[test]
+    fn disconnects_from_a_silent_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            write_frame(&mut stream, FRAME_HELLO, &hello(None, &[])).unwrap();
+            // Keep the socket open but stop sending, as a hung server does
+            (listener, stream)
+        });
+
+        let mut remote = RemoteTransport::connect(addr).unwrap();
+        let _server = server.join().unwrap();
+        let started = Instant::now();
+        let mut buf = [0u8; 6];
+        while remote.is_connected() {
+            assert!(
+                started.elapsed() < IDLE_TIMEOUT * 2,
+                "the link was never dropped"
+            );
+            remote.read_timeout(&mut buf, 100).unwrap();
+        }
+        assert!(started.elapsed() >= IDLE_TIMEOUT - Duration::from_millis(100));
+    }
+
+    #[test]
+    fn heartbeats_keep_an_idle_link_up() {
+        let panel = VirtualPanel::new();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let transport = Box::new(panel.transport().unwrap());
+        thread::spawn(move || serve(listener, transport));
+
+        let mut remote = RemoteTransport::connect(addr).unwrap();
+        let started = Instant::now();
+        let mut buf = [0u8; 6];
+        while started.elapsed() < IDLE_TIMEOUT + HEARTBEAT_INTERVAL {
+            assert_eq!(remote.read_timeout(&mut buf, 100).unwrap(), 0);
+        }
+        assert!(remote.is_connected());
+        assert_eq!(remote.reconnects(), 0);
+    }
+
+    #[test]
+    fn skips_addresses_without_a_server() {
+        // Answers with something other than a hello frame
+        let wrong = TcpListener::bind("127.0.0.1:0").unwrap();
+        let wrong_addr = wrong.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = wrong.accept().unwrap();
+            write_frame(&mut stream, FRAME_INPUT, &[0; 6]).unwrap();
+        });
+        let right = TcpListener::bind("127.0.0.1:0").unwrap();
+        let right_addr = right.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = right.accept().unwrap();
+            write_frame(&mut stream, FRAME_HELLO, &hello(Some("ABC123".into()), &[])).unwrap();
+            stream
+        });
+
+        let remote = RemoteTransport::connect(&[wrong_addr, right_addr][..]).unwrap();
+        assert_eq!(remote.serial_number().as_deref(), Some("ABC123"));
+    }
+
+    #[test]
+    fn hello_round_trip() {
+        let data = hello(Some("XYZ".into()), &[0x06, 0x00, 0xff]);
+        assert_eq!(
+            parse_hello(&data).unwrap(),
+            (Some("XYZ".to_string()), vec![0x06, 0x00, 0xff])
+        );
+        assert_eq!(parse_hello(&hello(None, &[])).unwrap(), (None, vec![]));
+        assert!(parse_hello(&[4, b'a']).is_err());
+    }
+}
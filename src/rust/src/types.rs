@@ -3,49 +3,11 @@
  * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
  */
 
-use std::fmt;
+//! The panel types, from [`beolyd5_core::types`], and the controller's own [`SystemEvent`] and
+//! [`ControllerState`].
 
-/// `Button` represents one of the four buttons on the BeoSound 5 controller.
-#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
-pub enum Button {
-    None,
-    Left,
-    Right,
-    Go,
-    Standby,
-}
-
-impl fmt::Display for Button {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            Button::None => write!(f, "None"),
-            Button::Left => write!(f, "Left"),
-            Button::Right => write!(f, "Right"),
-            Button::Go => write!(f, "Go"),
-            Button::Standby => write!(f, "Standby"),
-        }
-    }
-}
-
-/// `Wheel` represents one of the three wheels on the BeoSound 5 controller.
-#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
-pub enum Wheel {
-    Front,
-    Angular,
-    Back,
-    None,
-}
-
-impl fmt::Display for Wheel {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            Wheel::Front => write!(f, "Front"),
-            Wheel::Angular => write!(f, "Angular"),
-            Wheel::Back => write!(f, "Back"),
-            Wheel::None => write!(f, "None"),
-        }
-    }
-}
+pub use beolyd5_core::types::*;
+use std::time::Instant;
 
 /// `SystemEvent` represents a system event (any event) from the BeoSound 5 controller.
 /// It includes the event bytes, the last read bytes, the positions of the wheels, and the button pressed.
@@ -58,3 +20,29 @@ pub struct SystemEvent {
     pub back_wheel_pos: u8,
     pub button_pressed: Button,
 }
+
+/// `ControllerState` is a snapshot of what the controller knows about the panel, as returned by
+/// [`Beolyd5Controller::state`](crate::Beolyd5Controller::state).
+///
+/// Buttons and wheels are as delivered to the callbacks, after settings and filters. Times come
+/// from the controller's [`Clock`](crate::clock::Clock).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControllerState {
+    /// The controller is open and reading from the panel.
+    pub open: bool,
+    pub serial_number: Option<String>,
+    /// The button currently held down, or `Button::None`.
+    pub button_held: Button,
+    /// The last angular wheel position, or `None` if the pointer has not moved since the start.
+    pub angular_position: Option<u8>,
+    /// When each wheel last moved.
+    pub front_wheel_moved: Option<Instant>,
+    pub angular_wheel_moved: Option<Instant>,
+    pub back_wheel_moved: Option<Instant>,
+    /// When the last input report arrived, or when the controller was opened.
+    pub last_input: Instant,
+    /// The backlight was turned off by the idle timeout.
+    pub idle: bool,
+    /// The last output report sent to the panel.
+    pub output: PanelOutput,
+}
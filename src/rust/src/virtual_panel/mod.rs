@@ -0,0 +1,290 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! A virtual BeoSound 5 panel for development without the hardware.
+//!
+//! [`VirtualPanel`] keeps the state of the wheels and buttons and produces the same 6-byte input
+//! reports as the real device. Its [`VirtualTransport`] plugs into
+//! [`Beolyd5Controller::with_transport`](crate::Beolyd5Controller::with_transport), and any output
+//! report sent by the controller is recorded so LED, backlight and sound commands can be shown.
+//!
+//! With the `virtual-tui` feature, [`tui::run`] drives the panel from the keyboard in a terminal.
+
+use crate::decoder::{
+    ANGULAR_MAX, ANGULAR_WHEEL_BYTE, BACK_WHEEL_BYTE, BUTTON_BYTE, FRONT_WHEEL_BYTE, REPORT_LEN,
+};
+use crate::transport::Transport;
+use crate::types::{Button, PanelOutput, Wheel};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(feature = "virtual-tui")]
+pub mod tui;
+
+/// Number of notes kept by [`VirtualPanel::note`].
+const MAX_NOTES: usize = 8;
+
+/// `PanelState` is what the virtual panel currently shows and reports.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PanelState {
+    /// Angular wheel position, `0..=ANGULAR_MAX`.
+    pub angular: u8,
+    /// Buttons currently held down.
+    pub buttons: Vec<Button>,
+    /// The last output report received from the controller, decoded.
+    pub output: PanelOutput,
+    /// Number of output reports that made a click or a sound.
+    pub sounds: u64,
+    /// Free-form messages to show next to the panel, newest last.
+    pub notes: VecDeque<String>,
+}
+
+/// `VirtualPanel` is a software stand-in for the BeoSound 5 panel. Clones share the same panel.
+#[derive(Clone)]
+pub struct VirtualPanel {
+    state: Arc<Mutex<PanelState>>,
+    reports: Sender<[u8; REPORT_LEN]>,
+    receiver: Arc<Mutex<Option<Receiver<[u8; REPORT_LEN]>>>>,
+}
+
+impl Default for VirtualPanel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualPanel {
+    /// Creates a new panel with the pointer at the top and no buttons held.
+    pub fn new() -> VirtualPanel {
+        let (reports, receiver) = mpsc::channel();
+        VirtualPanel {
+            state: Arc::new(Mutex::new(PanelState::default())),
+            reports,
+            receiver: Arc::new(Mutex::new(Some(receiver))),
+        }
+    }
+
+    /// Returns the transport to hand to the controller. A panel only has one transport, so this
+    /// returns `None` when called a second time.
+    pub fn transport(&self) -> Option<VirtualTransport> {
+        let reports = self.receiver.lock().unwrap().take()?;
+        Some(VirtualTransport {
+            state: self.state.clone(),
+            reports,
+        })
+    }
+
+    /// Returns a copy of the current panel state.
+    pub fn state(&self) -> PanelState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Spins the front or back wheel by `steps`, negative steps spinning counter-clockwise.
+    /// Each step becomes its own report, as on the real panel.
+    pub fn spin(&self, wheel: Wheel, steps: i8) {
+        let byte = match wheel {
+            Wheel::Front => FRONT_WHEEL_BYTE,
+            Wheel::Back => BACK_WHEEL_BYTE,
+            Wheel::Angular => return self.move_pointer(steps as i16),
+            Wheel::None => return,
+        };
+
+        let step = if steps < 0 { -1i8 } else { 1i8 };
+        for _ in 0..steps.unsigned_abs() {
+            let mut report = self.idle_report();
+            report[byte] = step as u8;
+            self.emit(report);
+        }
+    }
+
+    /// Moves the angular pointer by `delta` positions, clamped to `0..=ANGULAR_MAX`.
+    pub fn move_pointer(&self, delta: i16) {
+        let current = self.state.lock().unwrap().angular as i16;
+        self.set_pointer((current + delta).clamp(0, ANGULAR_MAX as i16) as u8);
+    }
+
+    /// Puts the angular pointer at `position`, clamped to `0..=ANGULAR_MAX`.
+    pub fn set_pointer(&self, position: u8) {
+        let position = position.min(ANGULAR_MAX);
+        let mut state = self.state.lock().unwrap();
+        if state.angular == position {
+            return;
+        }
+        state.angular = position;
+        drop(state);
+
+        self.emit(self.idle_report());
+    }
+
+    /// Presses `button` and keeps it held until [`VirtualPanel::release`].
+    pub fn press(&self, button: Button) {
+        let mut state = self.state.lock().unwrap();
+        if button == Button::None || state.buttons.contains(&button) {
+            return;
+        }
+        state.buttons.push(button);
+        drop(state);
+
+        self.emit(self.idle_report());
+    }
+
+    /// Releases `button` if it is held.
+    pub fn release(&self, button: Button) {
+        let mut state = self.state.lock().unwrap();
+        let held = state.buttons.len();
+        state.buttons.retain(|b| *b != button);
+        if state.buttons.len() == held {
+            return;
+        }
+        drop(state);
+
+        self.emit(self.idle_report());
+    }
+
+    /// Presses and immediately releases `button`.
+    pub fn click(&self, button: Button) {
+        self.press(button);
+        self.release(button);
+    }
+
+    /// Adds a message to show next to the panel, e.g. the events an application received.
+    pub fn note(&self, message: impl Into<String>) {
+        let mut state = self.state.lock().unwrap();
+        state.notes.push_back(message.into());
+        while state.notes.len() > MAX_NOTES {
+            state.notes.pop_front();
+        }
+    }
+
+    /// Builds a report with the wheels idle and the current pointer and buttons.
+    fn idle_report(&self) -> [u8; REPORT_LEN] {
+        let state = self.state.lock().unwrap();
+        let mut report = [0u8; REPORT_LEN];
+        report[ANGULAR_WHEEL_BYTE] = state.angular;
+        report[BUTTON_BYTE] = state
+            .buttons
+            .iter()
+            .fold(0, |bits, b| bits | button_bit(*b));
+        report
+    }
+
+    fn emit(&self, report: [u8; REPORT_LEN]) {
+        // Nobody listening is fine, the panel keeps its state either way
+        let _ = self.reports.send(report);
+    }
+}
+
+fn button_bit(button: Button) -> u8 {
+    match button {
+        Button::None => 0x00,
+        Button::Left => 0x20,
+        Button::Right => 0x10,
+        Button::Go => 0x40,
+        Button::Standby => 0x80,
+    }
+}
+
+/// `VirtualTransport` connects a [`VirtualPanel`] to the controller.
+pub struct VirtualTransport {
+    state: Arc<Mutex<PanelState>>,
+    reports: Receiver<[u8; REPORT_LEN]>,
+}
+
+impl Transport for VirtualTransport {
+    fn read_timeout(&mut self, buf: &mut [u8], timeout_ms: i32) -> io::Result<usize> {
+        let report = if timeout_ms < 0 {
+            self.reports
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            self.reports
+                .recv_timeout(Duration::from_millis(timeout_ms as u64))
+        };
+
+        match report {
+            Ok(report) => {
+                let len = report.len().min(buf.len());
+                buf[..len].copy_from_slice(&report[..len]);
+                Ok(len)
+            }
+            Err(RecvTimeoutError::Timeout) => Ok(0),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "virtual panel was dropped",
+            )),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if data.len() < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "output reports are 2 bytes",
+            ));
+        }
+
+        let output = PanelOutput::from_report([data[0], data[1]]);
+        let mut state = self.state.lock().unwrap();
+        if output.click || output.sound != 0 {
+            state.sounds += 1;
+        }
+        state.output = output;
+
+        Ok(data.len())
+    }
+
+    fn report_descriptor(&self) -> io::Result<Vec<u8>> {
+        Ok(crate::descriptor::PANEL_DESCRIPTOR.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Led;
+
+    fn read(transport: &mut VirtualTransport) -> Option<[u8; REPORT_LEN]> {
+        let mut buf = [0u8; REPORT_LEN];
+        match transport.read_timeout(&mut buf, 0).unwrap() {
+            0 => None,
+            _ => Some(buf),
+        }
+    }
+
+    #[test]
+    fn produces_device_reports() {
+        let panel = VirtualPanel::new();
+        let mut transport = panel.transport().unwrap();
+
+        panel.spin(Wheel::Back, -2);
+        panel.set_pointer(200);
+        panel.click(Button::Go);
+
+        assert_eq!(read(&mut transport), Some([0, 0xff, 0, 0, 0, 0]));
+        assert_eq!(read(&mut transport), Some([0, 0xff, 0, 0, 0, 0]));
+        assert_eq!(read(&mut transport), Some([0, 0, ANGULAR_MAX, 0, 0, 0]));
+        assert_eq!(read(&mut transport), Some([0, 0, ANGULAR_MAX, 0x40, 0, 0]));
+        assert_eq!(read(&mut transport), Some([0, 0, ANGULAR_MAX, 0, 0, 0]));
+        assert_eq!(read(&mut transport), None);
+        assert!(panel.transport().is_none());
+    }
+
+    #[test]
+    fn records_output_reports() {
+        let panel = VirtualPanel::new();
+        let mut transport = panel.transport().unwrap();
+
+        transport.write(&[0xd0, 0x00]).unwrap();
+        transport.write(&[0x00, 0x31]).unwrap();
+
+        let state = panel.state();
+        assert_eq!(state.output.led, Led::Off);
+        assert_eq!(state.output.sound, 0x31);
+        assert_eq!(state.sounds, 1);
+    }
+}
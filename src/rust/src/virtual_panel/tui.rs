@@ -0,0 +1,146 @@
+/*
+ * Copyright (c) 2024. Lars Baunwall. All rights reserved.
+ * Use of this source code is governed by an Apache 2.0 license that can be found in the LICENSE file.
+ */
+
+//! Keyboard driven terminal front-end for [`VirtualPanel`].
+//!
+//! | Key               | Action                                 |
+//! |-------------------|----------------------------------------|
+//! | Up / Down         | Spin the front wheel                   |
+//! | Page Up / Down    | Spin the back wheel                    |
+//! | Left / Right      | Move the angular pointer               |
+//! | `s`               | Start or stop sweeping the pointer     |
+//! | `l`, `r`, Enter   | Press Left, Right and Go               |
+//! | `p`               | Press Standby                          |
+//! | `q`, Esc          | Quit                                   |
+
+use super::VirtualPanel;
+use crate::decoder::ANGULAR_MAX;
+use crate::types::{Button, Led, Wheel};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::{cursor, execute, queue, style, terminal};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// How often the screen is redrawn and the sweep advances.
+const FRAME: Duration = Duration::from_millis(30);
+
+/// Pointer positions moved per arrow key press.
+const POINTER_STEP: i16 = 2;
+
+/// Runs the terminal front-end until the user quits.
+pub fn run(panel: &VirtualPanel) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    let result = event_loop(panel, &mut stdout);
+
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn event_loop(panel: &VirtualPanel, out: &mut impl Write) -> io::Result<()> {
+    let mut sweep: Option<i16> = None;
+    let mut next_frame = Instant::now();
+
+    loop {
+        let timeout = next_frame.saturating_duration_since(Instant::now());
+        if event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Release {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Up => panel.spin(Wheel::Front, 1),
+                    KeyCode::Down => panel.spin(Wheel::Front, -1),
+                    KeyCode::PageUp => panel.spin(Wheel::Back, 1),
+                    KeyCode::PageDown => panel.spin(Wheel::Back, -1),
+                    KeyCode::Left => panel.move_pointer(-POINTER_STEP),
+                    KeyCode::Right => panel.move_pointer(POINTER_STEP),
+                    KeyCode::Char('s') => sweep = if sweep.is_some() { None } else { Some(1) },
+                    KeyCode::Char('l') => panel.click(Button::Left),
+                    KeyCode::Char('r') => panel.click(Button::Right),
+                    KeyCode::Enter => panel.click(Button::Go),
+                    KeyCode::Char('p') => panel.click(Button::Standby),
+                    _ => (),
+                }
+            }
+            continue;
+        }
+
+        if let Some(direction) = sweep {
+            let angular = panel.state().angular;
+            if (direction > 0 && angular >= ANGULAR_MAX) || (direction < 0 && angular == 0) {
+                sweep = Some(-direction);
+            }
+            panel.move_pointer(sweep.unwrap_or(direction));
+        }
+
+        draw(panel, out, sweep.is_some())?;
+        next_frame = Instant::now() + FRAME;
+    }
+}
+
+fn draw(panel: &VirtualPanel, out: &mut impl Write, sweeping: bool) -> io::Result<()> {
+    let state = panel.state();
+    let width = 40usize;
+    let marker = state.angular as usize * (width - 1) / ANGULAR_MAX as usize;
+    let bar: String = (0..width)
+        .map(|i| if i == marker { '|' } else { '.' })
+        .collect();
+    let buttons = [Button::Left, Button::Right, Button::Go, Button::Standby]
+        .iter()
+        .map(|b| {
+            if state.buttons.contains(b) {
+                format!("[{}]", b)
+            } else {
+                format!(" {} ", b)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let led = match state.output.led {
+        Led::Off => "( )",
+        Led::On => "(*)",
+        Led::Blink => "(~)",
+    };
+
+    let mut lines = vec![
+        "BeoSound 5 (virtual)".to_string(),
+        String::new(),
+        format!(
+            "Pointer    {} {:>3}/{}{}",
+            bar,
+            state.angular,
+            ANGULAR_MAX,
+            if sweeping { "  sweeping" } else { "" }
+        ),
+        format!("Buttons    {}", buttons),
+        format!("LED        {} {}", led, state.output.led),
+        format!(
+            "Backlight  {}",
+            if state.output.backlight { "on" } else { "off" }
+        ),
+        format!(
+            "Sound      {} played, last 0x{:02x}",
+            state.sounds, state.output.sound
+        ),
+        String::new(),
+    ];
+    lines.extend(state.notes.iter().cloned());
+    lines.push(String::new());
+    lines.push(
+        "Up/Down: front wheel  PgUp/PgDn: back wheel  Left/Right: pointer  s: sweep".to_string(),
+    );
+    lines.push("l/r/Enter/p: Left/Right/Go/Standby  q: quit".to_string());
+
+    queue!(out, terminal::Clear(terminal::ClearType::All))?;
+    for (row, line) in lines.iter().enumerate() {
+        queue!(out, cursor::MoveTo(0, row as u16), style::Print(line))?;
+    }
+    out.flush()
+}
@@ -2,17 +2,91 @@
 //!
 //! Handles the low-level telnet connection to HEOS devices.
 
-use std::io::{BufRead, BufReader, Write};
-use std::net::TcpStream;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use super::types::{HeosConfig, HeosError, HeosHeader, HeosResponse};
+use super::types::{url_decode, HeosConfig, HeosError, HeosEvent, HeosHeader, HeosResponse};
+
+/// How long to wait for the response to a command by default
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the event connection may be quiet before the listener checks it with a heartbeat
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// First wait before the event listener reconnects, doubled after every failed attempt
+const LISTENER_RETRY: Duration = Duration::from_secs(1);
+
+/// Longest wait between reconnect attempts of the event listener
+const LISTENER_RETRY_MAX: Duration = Duration::from_secs(30);
+
+/// Arguments that identify which command a response belongs to
+const KEY_PARAMS: &[&str] = &["pid", "gid", "sid", "cid", "SEQUENCE"];
+
+/// Callback receiving change events, shared by the listener thread and the command connection
+type EventSink = Arc<Mutex<dyn FnMut(HeosEvent) + Send>>;
 
 /// HEOS telnet client for sending commands and receiving responses
 pub struct HeosClient {
     config: HeosConfig,
-    stream: Arc<Mutex<Option<TcpStream>>>,
+    stream: Arc<Mutex<Option<Connection>>>,
+    listener: Arc<Mutex<Option<EventListener>>>,
+    response_timeout: Duration,
+    heartbeat_interval: Duration,
+}
+
+/// Command connection, keeping lines read ahead between commands
+struct Connection {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+/// Dedicated connection receiving change events on a background thread
+struct EventListener {
+    shared: Arc<ListenerShared>,
+    sink: EventSink,
+}
+
+/// State shared between an [`EventListener`] and its thread
+struct ListenerShared {
+    /// Host and port to listen to, changed by [`HeosClient::set_config`]
+    target: Mutex<(String, u16)>,
+    /// Current connection, shut down to stop the thread or to switch hosts
+    stream: Mutex<Option<TcpStream>>,
+    running: AtomicBool,
+    listening: AtomicBool,
+}
+
+impl ListenerShared {
+    /// Close the current connection, so the thread reconnects or exits
+    fn interrupt(&self) {
+        if let Some(stream) = self.stream.lock().unwrap().as_ref() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    /// Wait up to `duration`, returning early when stopped or the target changes
+    fn wait(&self, duration: Duration, target: &(String, u16)) {
+        let deadline = Instant::now() + duration;
+        while self.running.load(Ordering::SeqCst)
+            && *self.target.lock().unwrap() == *target
+            && Instant::now() < deadline
+        {
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+impl Drop for EventListener {
+    fn drop(&mut self) {
+        // Unblocks the listener thread, which then exits
+        self.shared.running.store(false, Ordering::SeqCst);
+        self.shared.interrupt();
+    }
 }
 
 impl HeosClient {
@@ -21,6 +95,9 @@ impl HeosClient {
         Self {
             config,
             stream: Arc::new(Mutex::new(None)),
+            listener: Arc::new(Mutex::new(None)),
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
         }
     }
 
@@ -30,10 +107,29 @@ impl HeosClient {
     }
 
     /// Update the configuration (e.g., change host or player_id)
+    ///
+    /// The command connection is reopened with the new config on the next command, and a
+    /// running event listener moves over to the new host.
     pub fn set_config(&mut self, config: HeosConfig) {
         self.config = config;
-        // Disconnect to force reconnect with new config
-        self.disconnect();
+        self.retarget();
+    }
+
+    /// Change only the host, keeping the port and player ID
+    ///
+    /// Like [`set_config`](Self::set_config), the connections move over to the new host.
+    pub fn set_host(&mut self, host: String) {
+        self.config.host = host;
+        self.retarget();
+    }
+
+    /// Drop the command connection and move a running event listener to the configured host
+    fn retarget(&self) {
+        *self.stream.lock().unwrap() = None;
+        if let Some(listener) = self.listener.lock().unwrap().as_ref() {
+            *listener.shared.target.lock().unwrap() = (self.config.host.clone(), self.config.port);
+            listener.shared.interrupt();
+        }
     }
 
     /// Get the current player ID
@@ -46,17 +142,34 @@ impl HeosClient {
         self.config.player_id = pid;
     }
 
+    /// Get how long a command waits for its response
+    pub fn response_timeout(&self) -> Duration {
+        self.response_timeout
+    }
+
+    /// Set how long a command waits for its response, including interim messages
+    pub fn set_response_timeout(&mut self, timeout: Duration) {
+        self.response_timeout = timeout;
+    }
+
+    /// Get how long the event connection may be quiet before it is checked
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.heartbeat_interval
+    }
+
+    /// Set how long the event connection may be quiet before it is checked, applied when the
+    /// event listener starts
+    pub fn set_heartbeat_interval(&mut self, interval: Duration) {
+        self.heartbeat_interval = interval;
+    }
+
     /// Connect to the HEOS device
     pub fn connect(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let addr = format!("{}:{}", self.config.host, self.config.port);
-        let stream = TcpStream::connect_timeout(&addr.parse()?, Duration::from_secs(5))?;
-
-        // Set read timeout
-        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
-        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+        let stream = open_stream(&self.config.host, self.config.port)?;
+        let reader = BufReader::new(stream.try_clone()?);
 
         let mut guard = self.stream.lock().unwrap();
-        *guard = Some(stream);
+        *guard = Some(Connection { stream, reader });
 
         Ok(())
     }
@@ -66,10 +179,11 @@ impl HeosClient {
         &self.config.host
     }
 
-    /// Disconnect from the HEOS device
+    /// Disconnect from the HEOS device, stopping the event listener too
     pub fn disconnect(&self) {
         let mut guard = self.stream.lock().unwrap();
         *guard = None;
+        self.stop_event_listener();
     }
 
     /// Check if connected
@@ -86,24 +200,156 @@ impl HeosClient {
         Ok(())
     }
 
+    /// Start listening for change events on a dedicated connection
+    ///
+    /// The HEOS spec recommends one connection for commands and another for events, so events
+    /// never get mixed up with command responses. Events are passed to `callback` on a
+    /// background thread until the listener is stopped. Events that arrive on the command
+    /// connection anyway are passed to `callback` as well.
+    ///
+    /// When the connection drops, or a heartbeat sent after a quiet heartbeat interval goes
+    /// unanswered, the listener reconnects with a growing delay and registers again. `on_status` is called with `true` every time it has registered for events, and with
+    /// `false` when the connection is lost or the listener stops.
+    pub fn start_event_listener<F, S>(
+        &self,
+        callback: F,
+        mut on_status: S,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnMut(HeosEvent) + Send + 'static,
+        S: FnMut(bool) + Send + 'static,
+    {
+        self.stop_event_listener();
+
+        // The first connection is made here, so a wrong host is reported to the caller
+        let target = (self.config.host.clone(), self.config.port);
+        let first = (register_for_events(&target.0, target.1)?, target.clone());
+
+        let shared = Arc::new(ListenerShared {
+            target: Mutex::new(target),
+            stream: Mutex::new(None),
+            running: AtomicBool::new(true),
+            listening: AtomicBool::new(false),
+        });
+        let sink: EventSink = Arc::new(Mutex::new(callback));
+        let heartbeat_interval = self.heartbeat_interval;
+        let thread_shared = shared.clone();
+        let thread_sink = sink.clone();
+        thread::spawn(move || {
+            let shared = thread_shared;
+            let mut next = Some(first);
+            let mut retry = LISTENER_RETRY;
+            while shared.running.load(Ordering::SeqCst) {
+                let (stream, target) = match next.take() {
+                    Some((stream, target)) => (Ok(stream), target),
+                    None => {
+                        let target = shared.target.lock().unwrap().clone();
+                        (register_for_events(&target.0, target.1), target)
+                    }
+                };
+                let Ok(stream) = stream else {
+                    shared.wait(retry, &target);
+                    retry = (retry * 2).min(LISTENER_RETRY_MAX);
+                    continue;
+                };
+                let Ok(reader) = stream.try_clone().map(BufReader::new) else {
+                    continue;
+                };
+                if stream.set_read_timeout(Some(heartbeat_interval)).is_err() {
+                    continue;
+                }
+
+                // Checked under the lock, so a stop or host change either sees this stream or
+                // is seen here
+                {
+                    let mut current = shared.stream.lock().unwrap();
+                    if !shared.running.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if *shared.target.lock().unwrap() != target {
+                        continue;
+                    }
+                    *current = Some(stream);
+                }
+                retry = LISTENER_RETRY;
+                shared.listening.store(true, Ordering::SeqCst);
+                on_status(true);
+
+                listen(reader, |event| (thread_sink.lock().unwrap())(event));
+
+                shared.listening.store(false, Ordering::SeqCst);
+                *shared.stream.lock().unwrap() = None;
+                on_status(false);
+                // Don't hammer a device that keeps closing the connection
+                shared.wait(LISTENER_RETRY, &target);
+            }
+        });
+
+        let mut guard = self.listener.lock().unwrap();
+        *guard = Some(EventListener { shared, sink });
+
+        Ok(())
+    }
+
+    /// Start listening for change events and receive them through a channel
+    pub fn subscribe_events(&self) -> Result<Receiver<HeosEvent>, Box<dyn std::error::Error>> {
+        let (tx, rx) = mpsc::channel();
+        self.start_event_listener(
+            move |event| {
+                let _ = tx.send(event);
+            },
+            |_| (),
+        )?;
+        Ok(rx)
+    }
+
+    /// Stop listening for change events
+    pub fn stop_event_listener(&self) {
+        let mut guard = self.listener.lock().unwrap();
+        *guard = None;
+    }
+
+    /// Check if the event listener is registered for events right now
+    pub fn is_listening(&self) -> bool {
+        let guard = self.listener.lock().unwrap();
+        guard
+            .as_ref()
+            .is_some_and(|listener| listener.shared.listening.load(Ordering::SeqCst))
+    }
+
     /// Send a raw HEOS command and return the raw JSON response
+    ///
+    /// Lines that do not answer the command are skipped: "command under process" messages,
+    /// responses to other commands, and change events, which go to the event listener if one
+    /// is running. Fails if no response arrives within the response timeout.
     pub fn send_command(&self, command: &str) -> Result<String, Box<dyn std::error::Error>> {
         self.ensure_connected()?;
 
         let mut guard = self.stream.lock().unwrap();
-        let stream = guard.as_mut().ok_or("Not connected")?;
+        let conn = guard.as_mut().ok_or("Not connected")?;
 
-        // Send command with CRLF terminator
-        let full_command = format!("{}\r\n", command);
-        stream.write_all(full_command.as_bytes())?;
-        stream.flush()?;
+        let sink = self
+            .listener
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|listener| listener.sink.clone());
+        let mut on_event = |event| {
+            if let Some(sink) = &sink {
+                (sink.lock().unwrap())(event);
+            }
+        };
 
-        // Read response until CRLF
-        let mut reader = BufReader::new(stream.try_clone()?);
-        let mut response = String::new();
-        reader.read_line(&mut response)?;
+        let deadline = Instant::now() + self.response_timeout;
+        let result = conn
+            .send(command)
+            .and_then(|_| conn.read_response(command, deadline, &mut on_event));
 
-        Ok(response.trim().to_string())
+        // Whatever is left of a failed exchange would be taken for the next response
+        if result.is_err() {
+            *guard = None;
+        }
+        result.map_err(|e| e.into())
     }
 
     /// Send a command and parse the response as JSON
@@ -164,13 +410,177 @@ impl Clone for HeosClient {
         Self {
             config: self.config.clone(),
             stream: Arc::new(Mutex::new(None)), // New instance gets its own connection
+            listener: Arc::new(Mutex::new(None)),
+            response_timeout: self.response_timeout,
+            heartbeat_interval: self.heartbeat_interval,
+        }
+    }
+}
+
+impl Connection {
+    /// Send a command with CRLF terminator
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        let full_command = format!("{}\r\n", command);
+        self.stream.write_all(full_command.as_bytes())?;
+        self.stream.flush()
+    }
+
+    /// Read lines until the response to `command` arrives or `deadline` passes
+    fn read_response(
+        &mut self,
+        command: &str,
+        deadline: Instant,
+        on_event: &mut dyn FnMut(HeosEvent),
+    ) -> io::Result<String> {
+        let expected = ExpectedResponse::new(command);
+        let timed_out = || {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("No response to {} in time", expected.command),
+            )
+        };
+
+        loop {
+            let remaining = deadline
+                .checked_duration_since(Instant::now())
+                .filter(|d| !d.is_zero())
+                .ok_or_else(timed_out)?;
+            self.stream.set_read_timeout(Some(remaining))?;
+
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(_) => (),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Err(timed_out())
+                }
+                Err(e) => return Err(e),
+            }
+
+            let line = line.trim();
+            if let Some(event) = HeosEvent::parse(line) {
+                on_event(event);
+                continue;
+            }
+            let Ok(response) = serde_json::from_str::<HeosResponse<serde_json::Value>>(line) else {
+                continue;
+            };
+            if response.heos.message.starts_with("command under process") {
+                continue;
+            }
+            if expected.matches(&response.heos) {
+                return Ok(line.to_string());
+            }
         }
     }
 }
 
+/// What the response to a command looks like
+struct ExpectedResponse {
+    /// Command without scheme and arguments, e.g. "player/get_volume"
+    command: String,
+    /// Key arguments of the command, decoded like the response message they are compared with
+    params: Vec<(String, String)>,
+}
+
+impl ExpectedResponse {
+    fn new(command: &str) -> Self {
+        let command = command.trim().trim_start_matches("heos://");
+        let (path, query) = command.split_once('?').unwrap_or((command, ""));
+        let params = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .filter(|(key, _)| KEY_PARAMS.contains(key))
+            .map(|(key, value)| (key.to_string(), url_decode(value)))
+            .collect();
+
+        Self {
+            command: path.to_string(),
+            params,
+        }
+    }
+
+    /// Check the command and key arguments, tolerating responses that leave an argument out
+    fn matches(&self, header: &HeosHeader) -> bool {
+        if header.command.trim() != self.command {
+            return false;
+        }
+        let msg = header.parse_message();
+        self.params
+            .iter()
+            .all(|(key, value)| msg.get(key).is_none_or(|v| v == value))
+    }
+}
+
+/// Open a new socket to the HEOS device
+fn open_stream(host: &str, port: u16) -> Result<TcpStream, Box<dyn std::error::Error>> {
+    if host.is_empty() {
+        return Err("No HEOS host set, discover devices or set the host first".into());
+    }
+    let addr = format!("{}:{}", host, port);
+    let stream = TcpStream::connect_timeout(&addr.parse()?, Duration::from_secs(5))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    Ok(stream)
+}
+
+/// Open a socket and register it for change events
+fn register_for_events(host: &str, port: u16) -> Result<TcpStream, Box<dyn std::error::Error>> {
+    let mut stream = open_stream(host, port)?;
+    stream.write_all(b"heos://system/register_for_change_events?enable=on\r\n")?;
+    stream.flush()?;
+    Ok(stream)
+}
+
+/// Read lines until the connection closes or stops answering, passing on the change events
+///
+/// The stream's read timeout is the heartbeat interval: when it passes without a line, a
+/// heartbeat is sent, and when it passes again the connection is given up.
+fn listen(mut reader: BufReader<TcpStream>, mut callback: impl FnMut(HeosEvent)) {
+    let mut line = String::new();
+    let mut heartbeat_sent = false;
+    loop {
+        match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => (),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                // A partial line stays in `line` until the rest arrives
+                if heartbeat_sent {
+                    break;
+                }
+                let mut stream = reader.get_ref();
+                if stream.write_all(b"heos://system/heart_beat\r\n").is_err() {
+                    break;
+                }
+                heartbeat_sent = true;
+                continue;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+
+        heartbeat_sent = false;
+        // The register response and anything else that is not an event is skipped
+        if let Some(event) = HeosEvent::parse(line.trim()) {
+            callback(event);
+        }
+        line.clear();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Read;
 
     #[test]
     fn test_header_parse_message() {
@@ -184,4 +594,250 @@ mod tests {
         assert_eq!(parsed.get("pid"), Some(&"123".to_string()));
         assert_eq!(parsed.get("level"), Some(&"50".to_string()));
     }
+
+    #[test]
+    fn test_event_listener_uses_own_connection() {
+        let speaker = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = HeosClient::new(HeosConfig {
+            host: "127.0.0.1".to_string(),
+            port: speaker.local_addr().unwrap().port(),
+            player_id: 0,
+        });
+
+        let events = client.subscribe_events().unwrap();
+        let (mut conn, _) = speaker.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(conn.try_clone().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        assert_eq!(
+            line,
+            "heos://system/register_for_change_events?enable=on\r\n"
+        );
+
+        conn.write_all(
+            b"{\"heos\": {\"command\": \"system/register_for_change_events\", \"result\": \"success\", \"message\": \"enable=on\"}}\r\n\
+              {\"heos\": {\"command\": \"event/player_volume_changed\", \"message\": \"pid=1&level=30&mute=off\"}}\r\n",
+        )
+        .unwrap();
+
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(
+            event,
+            HeosEvent::VolumeChanged {
+                pid: 1,
+                level: 30,
+                ..
+            }
+        ));
+        assert!(client.is_listening());
+        assert!(!client.is_connected());
+
+        client.stop_event_listener();
+        assert!(!client.is_listening());
+        assert!(events.recv_timeout(Duration::from_secs(5)).is_err());
+    }
+
+    /// Fake speaker on a local port, with a client configured for it
+    fn speaker() -> (std::net::TcpListener, HeosClient) {
+        let speaker = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = HeosClient::new(HeosConfig {
+            host: "127.0.0.1".to_string(),
+            port: speaker.local_addr().unwrap().port(),
+            player_id: 0,
+        });
+        (speaker, client)
+    }
+
+    #[test]
+    fn test_send_command_waits_for_matching_response() {
+        let (speaker, client) = speaker();
+        let events = client.subscribe_events().unwrap();
+        let (_events_conn, _) = speaker.accept().unwrap();
+
+        let answer = thread::spawn(move || {
+            let (mut conn, _) = speaker.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(conn.try_clone().unwrap())
+                .read_line(&mut line)
+                .unwrap();
+            assert_eq!(line, "heos://browse/browse?sid=1&SEQUENCE=7\r\n");
+
+            for reply in [
+                r#"{"heos": {"command": "event/player_queue_changed", "message": "pid=5"}}"#,
+                r#"{"heos": {"command": "browse/browse", "result": "success", "message": "command under process"}}"#,
+                r#"{"heos": {"command": "browse/browse", "result": "success", "message": "sid=2&SEQUENCE=6&returned=0&count=0"}}"#,
+                r#"{"heos": {"command": "browse/browse", "result": "success", "message": "sid=1&SEQUENCE=7&returned=0&count=0"}, "payload": []}"#,
+            ] {
+                conn.write_all(format!("{}\r\n", reply).as_bytes()).unwrap();
+            }
+            conn
+        });
+
+        let response = client
+            .send_command("heos://browse/browse?sid=1&SEQUENCE=7")
+            .unwrap();
+        let _conn = answer.join().unwrap();
+        assert!(response.contains("sid=1&SEQUENCE=7"), "{}", response);
+
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event, HeosEvent::QueueChanged { pid: 5 });
+    }
+
+    #[test]
+    fn test_send_command_matches_encoded_arguments() {
+        let (speaker, client) = speaker();
+
+        let answer = thread::spawn(move || {
+            let (mut conn, _) = speaker.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(conn.try_clone().unwrap())
+                .read_line(&mut line)
+                .unwrap();
+            assert_eq!(line, "heos://browse/browse?sid=1&cid=Rock%26Roll\r\n");
+
+            let reply = r#"{"heos": {"command": "browse/browse", "result": "success", "message": "sid=1&cid=Rock%26Roll&returned=0&count=0"}, "payload": []}"#;
+            conn.write_all(format!("{}\r\n", reply).as_bytes()).unwrap();
+            conn
+        });
+
+        let response = client
+            .send_command("heos://browse/browse?sid=1&cid=Rock%26Roll")
+            .unwrap();
+        let _conn = answer.join().unwrap();
+        assert!(response.contains("cid=Rock%26Roll"), "{}", response);
+    }
+
+    #[test]
+    fn test_send_command_times_out() {
+        let (speaker, mut client) = speaker();
+        client.set_response_timeout(Duration::from_millis(100));
+
+        let silent = thread::spawn(move || speaker.accept().unwrap().0);
+        let started = Instant::now();
+        let err = client.send_command("heos://system/heart_beat").unwrap_err();
+        let _conn = silent.join().unwrap();
+
+        assert!(err.to_string().contains("system/heart_beat"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!client.is_connected());
+    }
+
+    /// Accept the event connection and check that it registers for events
+    fn accept_registration(speaker: &std::net::TcpListener) -> TcpStream {
+        let (conn, _) = speaker.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(conn.try_clone().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        assert_eq!(
+            line,
+            "heos://system/register_for_change_events?enable=on\r\n"
+        );
+        conn
+    }
+
+    #[test]
+    fn test_event_listener_reconnects() {
+        let (speaker, client) = speaker();
+        let (tx, events) = mpsc::channel();
+        let (status_tx, status) = mpsc::channel();
+        client
+            .start_event_listener(
+                move |event| {
+                    let _ = tx.send(event);
+                },
+                move |listening| {
+                    let _ = status_tx.send(listening);
+                },
+            )
+            .unwrap();
+
+        // The speaker restarts, the listener registers again
+        drop(accept_registration(&speaker));
+        let mut conn = accept_registration(&speaker);
+        conn.write_all(
+            b"{\"heos\": {\"command\": \"event/players_changed\", \"message\": \"\"}}\r\n",
+        )
+        .unwrap();
+
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event, HeosEvent::PlayersChanged);
+        let timeout = Duration::from_secs(5);
+        let statuses: Vec<bool> = (0..3)
+            .map(|_| status.recv_timeout(timeout).unwrap())
+            .collect();
+        assert_eq!(statuses, vec![true, false, true]);
+        assert!(client.is_listening());
+    }
+
+    #[test]
+    fn test_event_listener_checks_quiet_connection() {
+        let (speaker, mut client) = speaker();
+        client.set_heartbeat_interval(Duration::from_millis(200));
+        let (status_tx, status) = mpsc::channel();
+        client
+            .start_event_listener(
+                |_| (),
+                move |listening| {
+                    let _ = status_tx.send(listening);
+                },
+            )
+            .unwrap();
+
+        // The first heartbeat is answered, the second is not
+        let mut conn = accept_registration(&speaker);
+        let mut reader = BufReader::new(conn.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "heos://system/heart_beat\r\n");
+        conn.write_all(
+            b"{\"heos\": {\"command\": \"system/heart_beat\", \"result\": \"success\", \"message\": \"\"}}\r\n",
+        )
+        .unwrap();
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "heos://system/heart_beat\r\n");
+
+        // The speaker hangs without closing the connection, the listener registers again
+        let _new = accept_registration(&speaker);
+        let timeout = Duration::from_secs(5);
+        let statuses: Vec<bool> = (0..3)
+            .map(|_| status.recv_timeout(timeout).unwrap())
+            .collect();
+        assert_eq!(statuses, vec![true, false, true]);
+    }
+
+    #[test]
+    fn test_set_host_keeps_port_and_player() {
+        let mut client = HeosClient::new(HeosConfig {
+            host: String::new(),
+            port: 1256,
+            player_id: 7,
+        });
+        client.set_host("192.168.1.20".to_string());
+
+        assert_eq!(client.host(), "192.168.1.20");
+        assert_eq!(client.config.port, 1256);
+        assert_eq!(client.player_id(), 7);
+    }
+
+    #[test]
+    fn test_event_listener_follows_host_change() {
+        let (first, mut client) = speaker();
+        let _events = client.subscribe_events().unwrap();
+        let mut old = accept_registration(&first);
+
+        let second = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        client.set_config(HeosConfig {
+            host: "127.0.0.1".to_string(),
+            port: second.local_addr().unwrap().port(),
+            player_id: 0,
+        });
+
+        let _new = accept_registration(&second);
+        let mut rest = Vec::new();
+        old.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(old.read_to_end(&mut rest).unwrap(), 0);
+    }
 }
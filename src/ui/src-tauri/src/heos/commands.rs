@@ -294,16 +294,15 @@ impl HeosClient {
         let header = self.send_command_simple(&cmd)?;
         let msg = header.parse_message();
 
-        let repeat = match msg.get("repeat").map(|s| s.as_str()) {
-            Some("on_one") => RepeatMode::One,
-            Some("on_all") => RepeatMode::All,
-            _ => RepeatMode::Off,
-        };
-
-        let shuffle = match msg.get("shuffle").map(|s| s.as_str()) {
-            Some("on") => ShuffleMode::On,
-            _ => ShuffleMode::Off,
-        };
+        let repeat = msg
+            .get("repeat")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(RepeatMode::Off);
+
+        let shuffle = msg
+            .get("shuffle")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(ShuffleMode::Off);
 
         Ok((repeat, shuffle))
     }
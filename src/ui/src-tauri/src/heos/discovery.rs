@@ -0,0 +1,363 @@
+//! HEOS Device Discovery
+//!
+//! Finds HEOS devices on the LAN with an SSDP M-SEARCH, as described in the Connection section
+//! of the HEOS CLI spec, and reads their friendly names from the UPnP device description.
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// SSDP multicast group and port
+pub const SSDP_ADDR: &str = "239.255.255.250:1900";
+
+/// Search target answered by HEOS devices
+pub const SEARCH_TARGET: &str = "urn:schemas-denon-com:device:ACT-Denon:1";
+
+/// How long to collect search responses by default
+pub const DEFAULT_DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long reading the device descriptions may take, for all devices together
+const DESCRIPTION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// HEOS device found on the network
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeosDevice {
+    /// IP address to connect the CLI to
+    pub host: String,
+    /// Name given to the device in the HEOS app, or the host if the description was unavailable
+    pub friendly_name: String,
+    #[serde(default)]
+    pub model_name: Option<String>,
+    /// URL of the UPnP device description
+    pub location: String,
+}
+
+/// Search the LAN for HEOS devices, collecting responses for `timeout`
+pub fn discover(timeout: Duration) -> Result<Vec<HeosDevice>, Box<dyn std::error::Error>> {
+    discover_at(SSDP_ADDR.parse()?, timeout)
+}
+
+/// Send the M-SEARCH to `target` instead of the multicast group, e.g. a single device
+pub fn discover_at(
+    target: SocketAddr,
+    timeout: Duration,
+) -> Result<Vec<HeosDevice>, Box<dyn std::error::Error>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_multicast_ttl_v4(2)?;
+    socket.send_to(search_request().as_bytes(), target)?;
+
+    // Devices answer once per network interface, so keep the first response per location
+    let mut found: Vec<(String, SocketAddr)> = Vec::new();
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0u8; 2048];
+    while let Some(remaining) = deadline
+        .checked_duration_since(Instant::now())
+        .filter(|d| !d.is_zero())
+    {
+        socket.set_read_timeout(Some(remaining))?;
+        let (len, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                break
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let response = String::from_utf8_lossy(&buf[..len]);
+        if let Some(location) = parse_search_response(&response) {
+            if !found.iter().any(|(l, _)| *l == location) {
+                found.push((location, from));
+            }
+        }
+    }
+
+    // Read the descriptions side by side, so unreachable devices don't add up
+    let deadline = Instant::now() + DESCRIPTION_TIMEOUT;
+    let devices = thread::scope(|scope| {
+        let fetches: Vec<_> = found
+            .into_iter()
+            .map(|(location, from)| {
+                scope.spawn(move || {
+                    let host = url_host(&location).unwrap_or_else(|| from.ip().to_string());
+                    let description = fetch(&location, deadline).unwrap_or_default();
+                    HeosDevice {
+                        friendly_name: xml_text(&description, "friendlyName")
+                            .unwrap_or_else(|| host.clone()),
+                        model_name: xml_text(&description, "modelName"),
+                        host,
+                        location,
+                    }
+                })
+            })
+            .collect();
+        fetches
+            .into_iter()
+            .filter_map(|fetch| fetch.join().ok())
+            .collect()
+    });
+
+    Ok(devices)
+}
+
+/// M-SEARCH request for HEOS devices
+fn search_request() -> String {
+    format!(
+        "M-SEARCH * HTTP/1.1\r\n\
+         HOST: {}\r\n\
+         MAN: \"ssdp:discover\"\r\n\
+         MX: 1\r\n\
+         ST: {}\r\n\
+         \r\n",
+        SSDP_ADDR, SEARCH_TARGET
+    )
+}
+
+/// Return the LOCATION of a successful search response for HEOS devices
+fn parse_search_response(response: &str) -> Option<String> {
+    let mut lines = response.lines();
+    let status = lines.next()?;
+    if !status.starts_with("HTTP/1.1 200") {
+        return None;
+    }
+
+    let mut location = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_uppercase().as_str() {
+            "ST" if value != SEARCH_TARGET => return None,
+            "LOCATION" => location = Some(value.to_string()),
+            _ => (),
+        }
+    }
+
+    location
+}
+
+/// Host part of an http URL, without port
+fn url_host(url: &str) -> Option<String> {
+    let rest = url.split_once("://")?.1;
+    let authority = rest.split('/').next()?;
+    let host = authority
+        .rsplit_once(':')
+        .map_or(authority, |(host, _)| host);
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+/// GET an http URL and return the body, giving up at `deadline`
+fn fetch(url: &str, deadline: Instant) -> Result<String, Box<dyn std::error::Error>> {
+    let remaining = || {
+        deadline
+            .checked_duration_since(Instant::now())
+            .filter(|d| !d.is_zero())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "Fetching timed out"))
+    };
+
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or("Only http locations are supported")?;
+    let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let addr = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    let addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or("No address for location")?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, remaining()?)?;
+    stream.set_write_timeout(Some(remaining()?))?;
+    // HTTP/1.0 so the body is neither chunked nor kept alive
+    let request = format!(
+        "GET /{} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, authority
+    );
+    stream.write_all(request.as_bytes())?;
+
+    let mut response = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        stream.set_read_timeout(Some(remaining()?))?;
+        match stream.read(&mut chunk)? {
+            0 => break,
+            n => response.extend_from_slice(&chunk[..n]),
+        }
+    }
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or("Malformed HTTP response")?;
+    if head.split_whitespace().nth(1) != Some("200") {
+        return Err(format!(
+            "Fetching {} failed: {}",
+            url,
+            head.lines().next().unwrap_or("")
+        )
+        .into());
+    }
+
+    Ok(body.to_string())
+}
+
+/// Text of the first `<tag>` element, unescaped
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    let text = xml[start..end]
+        .trim()
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    const DESCRIPTION: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <device>
+    <deviceType>urn:schemas-denon-com:device:ACT-Denon:1</deviceType>
+    <friendlyName>Living Room &amp; Kitchen</friendlyName>
+    <manufacturer>Denon</manufacturer>
+    <modelName>HEOS Link</modelName>
+  </device>
+</root>"#;
+
+    fn search_response(location: &str, st: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\n\
+             CACHE-CONTROL: max-age=180\r\n\
+             EXT:\r\n\
+             location: {}\r\n\
+             SERVER: LINUX UPnP/1.0 Denon-Heos/149200\r\n\
+             ST: {}\r\n\
+             USN: uuid:5d4f7c2e-0000-0000-0000-000000000000::{}\r\n\
+             \r\n",
+            location, st, st
+        )
+    }
+
+    #[test]
+    fn test_parse_search_response() {
+        let location = "http://192.168.1.20:60006/upnp/desc/aios_device/aios_device.xml";
+        assert_eq!(
+            parse_search_response(&search_response(location, SEARCH_TARGET)),
+            Some(location.to_string())
+        );
+        assert_eq!(
+            parse_search_response(&search_response(location, "upnp:rootdevice")),
+            None
+        );
+        assert_eq!(url_host(location), Some("192.168.1.20".to_string()));
+    }
+
+    #[test]
+    fn test_discover_against_local_responder() {
+        // Serves the device description once
+        let http = TcpListener::bind("127.0.0.1:0").unwrap();
+        let location = format!(
+            "http://127.0.0.1:{}/upnp/desc/aios_device/aios_device.xml",
+            http.local_addr().unwrap().port()
+        );
+        let server = thread::spawn(move || {
+            let (mut conn, _) = http.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let len = conn.read(&mut request).unwrap();
+            assert!(String::from_utf8_lossy(&request[..len])
+                .starts_with("GET /upnp/desc/aios_device/aios_device.xml HTTP/1.0\r\n"));
+            let response = format!(
+                "HTTP/1.0 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\n\r\n{}",
+                DESCRIPTION.len(),
+                DESCRIPTION
+            );
+            conn.write_all(response.as_bytes()).unwrap();
+        });
+
+        // Answers the search twice, as devices on two interfaces do, plus an unrelated device
+        let responder = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = responder.local_addr().unwrap();
+        let ssdp_location = location.clone();
+        let ssdp = thread::spawn(move || {
+            let mut buf = [0u8; 1024];
+            let (len, from) = responder.recv_from(&mut buf).unwrap();
+            let request = String::from_utf8_lossy(&buf[..len]).to_string();
+            assert!(request.starts_with("M-SEARCH * HTTP/1.1\r\n"));
+            assert!(request.contains(&format!("ST: {}\r\n", SEARCH_TARGET)));
+
+            for st in [SEARCH_TARGET, SEARCH_TARGET, "upnp:rootdevice"] {
+                let response = search_response(&ssdp_location, st);
+                responder.send_to(response.as_bytes(), from).unwrap();
+            }
+        });
+
+        let devices = discover_at(target, Duration::from_millis(500)).unwrap();
+        ssdp.join().unwrap();
+        server.join().unwrap();
+
+        assert_eq!(
+            devices,
+            vec![HeosDevice {
+                host: "127.0.0.1".to_string(),
+                friendly_name: "Living Room & Kitchen".to_string(),
+                model_name: Some("HEOS Link".to_string()),
+                location,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_silent_devices_share_the_description_deadline() {
+        // Accept connections but never answer
+        let silent: Vec<TcpListener> = (0..2)
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let locations: Vec<String> = silent
+            .iter()
+            .map(|l| {
+                format!(
+                    "http://127.0.0.1:{}/desc.xml",
+                    l.local_addr().unwrap().port()
+                )
+            })
+            .collect();
+
+        let responder = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = responder.local_addr().unwrap();
+        let ssdp = thread::spawn(move || {
+            let mut buf = [0u8; 1024];
+            let (_, from) = responder.recv_from(&mut buf).unwrap();
+            for location in &locations {
+                let response = search_response(location, SEARCH_TARGET);
+                responder.send_to(response.as_bytes(), from).unwrap();
+            }
+        });
+
+        let started = Instant::now();
+        let devices = discover_at(target, Duration::from_millis(200)).unwrap();
+        ssdp.join().unwrap();
+
+        assert_eq!(devices.len(), 2);
+        assert!(devices.iter().all(|d| d.friendly_name == "127.0.0.1"));
+        assert!(started.elapsed() < DESCRIPTION_TIMEOUT * 2);
+    }
+}
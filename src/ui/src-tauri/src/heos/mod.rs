@@ -2,10 +2,13 @@
 //!
 //! This module provides a Rust implementation of the HEOS CLI protocol
 //! for controlling HEOS-enabled devices over telnet (port 1255).
+//! Devices are found on the LAN through SSDP discovery.
 
 pub mod client;
 pub mod commands;
+pub mod discovery;
 pub mod types;
 
 pub use client::HeosClient;
+pub use discovery::{discover, HeosDevice};
 pub use types::*;
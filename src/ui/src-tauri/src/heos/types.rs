@@ -7,6 +7,7 @@ use serde::{Deserialize, Serialize};
 /// HEOS connection configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HeosConfig {
+    /// Empty until set or found through discovery
     pub host: String,
     pub port: u16,
     pub player_id: i64,
@@ -15,7 +16,7 @@ pub struct HeosConfig {
 impl Default for HeosConfig {
     fn default() -> Self {
         Self {
-            host: "192.168.1.2".to_string(),
+            host: String::new(),
             port: 1255,
             player_id: 0,
         }
@@ -34,6 +35,8 @@ pub struct HeosResponse<T> {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HeosHeader {
     pub command: String,
+    /// Missing in change events
+    #[serde(default)]
     pub result: String,
     #[serde(default)]
     pub message: String,
@@ -46,13 +49,14 @@ impl HeosHeader {
 
     /// Parse message string into key-value pairs
     /// Format: "pid=123&volume=50&mute=off"
+    /// Values are URL-decoded, as '&', '=' and '%' are sent as %26, %3D and %25
     pub fn parse_message(&self) -> std::collections::HashMap<String, String> {
         self.message
             .split('&')
             .filter_map(|pair| {
                 let mut parts = pair.splitn(2, '=');
                 match (parts.next(), parts.next()) {
-                    (Some(key), Some(value)) => Some((key.to_string(), value.to_string())),
+                    (Some(key), Some(value)) => Some((key.to_string(), url_decode(value))),
                     _ => None,
                 }
             })
@@ -60,6 +64,31 @@ impl HeosHeader {
     }
 }
 
+/// Decode %XX escapes, leaving malformed ones as they are
+pub(super) fn url_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
 // ============================================================================
 // Player Types
 // ============================================================================
@@ -115,6 +144,7 @@ impl std::str::FromStr for PlayState {
 
 /// Mute state
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum MuteState {
     On,
     Off,
@@ -160,8 +190,22 @@ impl std::fmt::Display for RepeatMode {
     }
 }
 
+impl std::str::FromStr for RepeatMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(RepeatMode::Off),
+            "on_one" => Ok(RepeatMode::One),
+            "on_all" => Ok(RepeatMode::All),
+            _ => Err(format!("Unknown repeat mode: {}", s)),
+        }
+    }
+}
+
 /// Shuffle mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ShuffleMode {
     On,
     Off,
@@ -176,6 +220,18 @@ impl std::fmt::Display for ShuffleMode {
     }
 }
 
+impl std::str::FromStr for ShuffleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "on" => Ok(ShuffleMode::On),
+            "off" => Ok(ShuffleMode::Off),
+            _ => Err(format!("Unknown shuffle mode: {}", s)),
+        }
+    }
+}
+
 // ============================================================================
 // Now Playing Types
 // ============================================================================
@@ -272,29 +328,51 @@ pub enum AddCriteria {
 // Event Types (for change events)
 // ============================================================================
 
-/// HEOS change event types
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// HEOS change event, sent unsolicited once registered for change events
+///
+/// Events arrive as `{"heos": {"command": "event/...", "message": "pid=1&..."}}`, so they are
+/// read with [`HeosEvent::parse`]. The serialized form is for the frontend and is tagged with
+/// the event command.
+#[derive(Debug, Clone, PartialEq, Serialize)]
 #[serde(tag = "command")]
 pub enum HeosEvent {
     #[serde(rename = "event/player_state_changed")]
-    PlayerStateChanged { pid: i64, state: String },
+    PlayerStateChanged { pid: i64, state: PlayState },
 
     #[serde(rename = "event/player_now_playing_changed")]
     NowPlayingChanged { pid: i64 },
 
+    /// Positions are in milliseconds. Streams without a known length have no duration
     #[serde(rename = "event/player_now_playing_progress")]
     NowPlayingProgress {
         pid: i64,
         cur_pos: i64,
-        duration: i64,
+        duration: Option<i64>,
     },
 
     #[serde(rename = "event/player_volume_changed")]
-    VolumeChanged { pid: i64, level: u8, mute: String },
+    VolumeChanged {
+        pid: i64,
+        level: u8,
+        mute: MuteState,
+    },
 
     #[serde(rename = "event/player_queue_changed")]
     QueueChanged { pid: i64 },
 
+    #[serde(rename = "event/repeat_mode_changed")]
+    RepeatModeChanged { pid: i64, repeat: RepeatMode },
+
+    #[serde(rename = "event/shuffle_mode_changed")]
+    ShuffleModeChanged { pid: i64, shuffle: ShuffleMode },
+
+    #[serde(rename = "event/group_volume_changed")]
+    GroupVolumeChanged {
+        gid: i64,
+        level: u8,
+        mute: MuteState,
+    },
+
     #[serde(rename = "event/players_changed")]
     PlayersChanged,
 
@@ -304,10 +382,79 @@ pub enum HeosEvent {
     #[serde(rename = "event/sources_changed")]
     SourcesChanged,
 
+    /// `username` is set when a user signed in
+    #[serde(rename = "event/user_changed")]
+    UserChanged {
+        signed_in: bool,
+        username: Option<String>,
+    },
+
+    /// The error text can be shown to the user as is
     #[serde(rename = "event/player_playback_error")]
     PlaybackError { pid: i64, error: String },
 }
 
+impl HeosEvent {
+    /// Parse a line received from the device, returning `None` if it is not a change event
+    ///
+    /// Unknown events and events missing a required argument are skipped as well.
+    pub fn parse(line: &str) -> Option<HeosEvent> {
+        let response: HeosResponse<serde_json::Value> = serde_json::from_str(line).ok()?;
+        let header = response.heos;
+        let msg = header.parse_message();
+        let arg = |key: &str| msg.get(key);
+        let num = |key: &str| arg(key)?.parse::<i64>().ok();
+
+        // The spec examples pad some commands with spaces
+        let event = match header.command.trim() {
+            "event/player_state_changed" => HeosEvent::PlayerStateChanged {
+                pid: num("pid")?,
+                state: arg("state")?.parse().ok()?,
+            },
+            "event/player_now_playing_changed" => HeosEvent::NowPlayingChanged { pid: num("pid")? },
+            "event/player_now_playing_progress" => HeosEvent::NowPlayingProgress {
+                pid: num("pid")?,
+                cur_pos: num("cur_pos")?,
+                duration: num("duration").filter(|d| *d > 0),
+            },
+            "event/player_volume_changed" => HeosEvent::VolumeChanged {
+                pid: num("pid")?,
+                level: arg("level")?.parse().ok()?,
+                mute: arg("mute")?.parse().ok()?,
+            },
+            "event/player_queue_changed" => HeosEvent::QueueChanged { pid: num("pid")? },
+            "event/repeat_mode_changed" => HeosEvent::RepeatModeChanged {
+                pid: num("pid")?,
+                repeat: arg("repeat")?.parse().ok()?,
+            },
+            "event/shuffle_mode_changed" => HeosEvent::ShuffleModeChanged {
+                pid: num("pid")?,
+                shuffle: arg("shuffle")?.parse().ok()?,
+            },
+            "event/group_volume_changed" => HeosEvent::GroupVolumeChanged {
+                gid: num("gid")?,
+                level: arg("level")?.parse().ok()?,
+                mute: arg("mute")?.parse().ok()?,
+            },
+            "event/players_changed" => HeosEvent::PlayersChanged,
+            "event/groups_changed" => HeosEvent::GroupsChanged,
+            "event/sources_changed" => HeosEvent::SourcesChanged,
+            // The message is "signed_out" or "signed_in&un=<user name>"
+            "event/user_changed" => HeosEvent::UserChanged {
+                signed_in: header.message.split('&').next()? == "signed_in",
+                username: arg("un").cloned(),
+            },
+            "event/player_playback_error" => HeosEvent::PlaybackError {
+                pid: num("pid")?,
+                error: arg("error")?.clone(),
+            },
+            _ => return None,
+        };
+
+        Some(event)
+    }
+}
+
 // ============================================================================
 // Error Types
 // ============================================================================
@@ -345,3 +492,162 @@ pub mod error_codes {
     pub const TOO_MANY_REQUESTS: i32 = 15;
     pub const COMMAND_NOT_PROCESSED: i32 = 16;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wrap a command and message the way the device sends events
+    fn event(command: &str, message: Option<&str>) -> Option<HeosEvent> {
+        let mut heos = serde_json::json!({ "command": command });
+        if let Some(message) = message {
+            heos["message"] = message.into();
+        }
+        HeosEvent::parse(&serde_json::json!({ "heos": heos }).to_string())
+    }
+
+    // Payloads from the Change Events section of HEOS_CLI_MARKDOWN.md, including the stray
+    // spaces around some commands, with the placeholders filled in
+    #[test]
+    fn test_parse_spec_events() {
+        let cases = [
+            ("event/sources_changed", None, HeosEvent::SourcesChanged),
+            ("event/players_changed", None, HeosEvent::PlayersChanged),
+            ("event/groups_changed", None, HeosEvent::GroupsChanged),
+            (
+                "event/player_state_changed",
+                Some("pid=-1428708007&state=pause"),
+                HeosEvent::PlayerStateChanged {
+                    pid: -1428708007,
+                    state: PlayState::Pause,
+                },
+            ),
+            (
+                " event/player_now_playing_changed",
+                Some("pid=5"),
+                HeosEvent::NowPlayingChanged { pid: 5 },
+            ),
+            (
+                " event/player_now_playing_progress",
+                Some("pid=5&cur_pos=61000&duration=213000"),
+                HeosEvent::NowPlayingProgress {
+                    pid: 5,
+                    cur_pos: 61000,
+                    duration: Some(213000),
+                },
+            ),
+            (
+                " event/player_playback_error",
+                Some("pid=5&error=Could Not Download"),
+                HeosEvent::PlaybackError {
+                    pid: 5,
+                    error: "Could Not Download".to_string(),
+                },
+            ),
+            (
+                " event/player_queue_changed",
+                Some("pid=5"),
+                HeosEvent::QueueChanged { pid: 5 },
+            ),
+            (
+                "event/player_volume_changed ",
+                Some("pid=5&level=42&mute=off"),
+                HeosEvent::VolumeChanged {
+                    pid: 5,
+                    level: 42,
+                    mute: MuteState::Off,
+                },
+            ),
+            (
+                "event/repeat_mode_changed",
+                Some("pid=5&repeat=on_all"),
+                HeosEvent::RepeatModeChanged {
+                    pid: 5,
+                    repeat: RepeatMode::All,
+                },
+            ),
+            (
+                "event/shuffle_mode_changed",
+                Some("pid=5&shuffle=on"),
+                HeosEvent::ShuffleModeChanged {
+                    pid: 5,
+                    shuffle: ShuffleMode::On,
+                },
+            ),
+            (
+                "event/group_volume_changed ",
+                Some("gid=-1899423658&level=20&mute=on"),
+                HeosEvent::GroupVolumeChanged {
+                    gid: -1899423658,
+                    level: 20,
+                    mute: MuteState::On,
+                },
+            ),
+            (
+                "event/user_changed",
+                Some("signed_out"),
+                HeosEvent::UserChanged {
+                    signed_in: false,
+                    username: None,
+                },
+            ),
+            (
+                "event/user_changed",
+                Some("signed_in&un=lars@example.com"),
+                HeosEvent::UserChanged {
+                    signed_in: true,
+                    username: Some("lars@example.com".to_string()),
+                },
+            ),
+        ];
+
+        for (command, message, expected) in cases {
+            assert_eq!(event(command, message), Some(expected), "{}", command);
+        }
+    }
+
+    #[test]
+    fn test_parse_progress_without_duration() {
+        let expected = Some(HeosEvent::NowPlayingProgress {
+            pid: 5,
+            cur_pos: 1500,
+            duration: None,
+        });
+        assert_eq!(
+            event(
+                "event/player_now_playing_progress",
+                Some("pid=5&cur_pos=1500")
+            ),
+            expected
+        );
+        assert_eq!(
+            event(
+                "event/player_now_playing_progress",
+                Some("pid=5&cur_pos=1500&duration=0")
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_parse_skips_responses_and_unknown_events() {
+        let response = r#"{"heos": {"command": "player/get_volume", "result": "success", "message": "pid=5&level=42"}}"#;
+        assert_eq!(HeosEvent::parse(response), None);
+        assert_eq!(event("event/player_state_changed", Some("pid=5")), None);
+        assert_eq!(event("event/something_new", Some("pid=5")), None);
+        assert_eq!(HeosEvent::parse("not json"), None);
+    }
+
+    #[test]
+    fn test_parse_message_decodes_special_characters() {
+        let header = HeosHeader {
+            command: "event/player_playback_error".to_string(),
+            result: String::new(),
+            message: "pid=5&error=Rock %26 Roll %3D 100%25".to_string(),
+        };
+        assert_eq!(
+            header.parse_message().get("error"),
+            Some(&"Rock & Roll = 100%".to_string())
+        );
+    }
+}
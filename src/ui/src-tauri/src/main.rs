@@ -4,10 +4,13 @@
 mod heos;
 mod hw_controller;
 
-use heos::{HeosClient, HeosConfig, MusicSource, MuteState, NowPlayingMedia, Player};
+use heos::discovery::DEFAULT_DISCOVERY_TIMEOUT;
+use heos::{
+    discover, HeosClient, HeosConfig, HeosDevice, MusicSource, MuteState, NowPlayingMedia, Player,
+};
 use hw_controller::HWController;
 use std::sync::Mutex;
-use tauri::{Manager, State};
+use tauri::{AppHandle, Emitter, Manager, State};
 
 // ============================================================================
 // Hardware Controller Commands
@@ -30,6 +33,22 @@ async fn heos_connect(state: State<'_, Mutex<HeosClient>>) -> Result<(), String>
     client.connect().map_err(|e| e.to_string())
 }
 
+/// Search the LAN for HEOS devices
+#[tauri::command]
+async fn heos_discover() -> Result<Vec<HeosDevice>, String> {
+    discover(DEFAULT_DISCOVERY_TIMEOUT).map_err(|e| e.to_string())
+}
+
+/// Find the host of the first HEOS device on the LAN
+fn discover_host() -> Result<String, String> {
+    let device = discover(DEFAULT_DISCOVERY_TIMEOUT)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .next()
+        .ok_or_else(|| "No HEOS devices found on the network".to_string())?;
+    Ok(device.host)
+}
+
 #[tauri::command]
 async fn heos_set_host(state: State<'_, Mutex<HeosClient>>, host: String) -> Result<(), String> {
     let mut client = state.lock().map_err(|e| e.to_string())?;
@@ -54,7 +73,21 @@ async fn heos_connect_and_discover(
     state: State<'_, Mutex<HeosClient>>,
     player_name: Option<String>,
 ) -> Result<Player, String> {
+    // Find a device first if no host is set yet, without holding up other commands
+    let needs_host = state.lock().map_err(|e| e.to_string())?.host().is_empty();
+    let discovered = if needs_host {
+        Some(discover_host()?)
+    } else {
+        None
+    };
+
     let mut client = state.lock().map_err(|e| e.to_string())?;
+    if let Some(host) = discovered {
+        // A host set while discovering wins
+        if client.host().is_empty() {
+            client.set_host(host);
+        }
+    }
 
     // Connect if not already connected
     if !client.is_connected() {
@@ -92,6 +125,47 @@ async fn heos_disconnect(state: State<'_, Mutex<HeosClient>>) -> Result<(), Stri
     Ok(())
 }
 
+/// Start forwarding HEOS change events to the frontend as `heosEvent`
+///
+/// `heosEventsStopped` is emitted when the event connection is lost or the listener stops, and
+/// `heosEventsStarted` once it has registered for events again.
+#[tauri::command]
+async fn heos_start_events(
+    app: AppHandle,
+    state: State<'_, Mutex<HeosClient>>,
+) -> Result<(), String> {
+    let client = state.lock().map_err(|e| e.to_string())?;
+    let status_app = app.clone();
+    client
+        .start_event_listener(
+            move |event| {
+                let _ = app.emit("heosEvent", event);
+            },
+            move |listening| {
+                let name = if listening {
+                    "heosEventsStarted"
+                } else {
+                    "heosEventsStopped"
+                };
+                let _ = status_app.emit(name, ());
+            },
+        )
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn heos_stop_events(state: State<'_, Mutex<HeosClient>>) -> Result<(), String> {
+    let client = state.lock().map_err(|e| e.to_string())?;
+    client.stop_event_listener();
+    Ok(())
+}
+
+#[tauri::command]
+async fn heos_is_listening(state: State<'_, Mutex<HeosClient>>) -> Result<bool, String> {
+    let client = state.lock().map_err(|e| e.to_string())?;
+    Ok(client.is_listening())
+}
+
 #[tauri::command]
 async fn heos_heartbeat(state: State<'_, Mutex<HeosClient>>) -> Result<(), String> {
     let client = state.lock().map_err(|e| e.to_string())?;
@@ -246,12 +320,9 @@ async fn heos_clear_queue(state: State<'_, Mutex<HeosClient>>) -> Result<(), Str
 // ============================================================================
 
 fn main() {
-    // HEOS configuration - player_id will be set dynamically via heos_connect_and_discover
-    let heos_config = HeosConfig {
-        host: "192.168.1.2".to_string(), // Default, can be changed via heos_set_host
-        port: 1255,
-        player_id: 0, // Will be set dynamically
-    };
+    // HEOS configuration - host is found through discovery or set via heos_set_host,
+    // player_id will be set dynamically via heos_connect_and_discover
+    let heos_config = HeosConfig::default();
 
     tauri::Builder::default()
         .invoke_handler(tauri::generate_handler![
@@ -263,7 +334,12 @@ fn main() {
             heos_heartbeat,
             heos_set_host,
             heos_get_host,
+            heos_discover,
             heos_connect_and_discover,
+            // HEOS change events
+            heos_start_events,
+            heos_stop_events,
+            heos_is_listening,
             // HEOS player discovery
             heos_get_players,
             heos_set_player_id,
@@ -318,6 +394,23 @@ fn main() {
             let heos_client = HeosClient::new(heos_config);
             app.manage(Mutex::new(heos_client));
 
+            // Find the speaker in the background, unless a host was set in the meantime
+            let discovery_handle = app_handle.clone();
+            std::thread::spawn(move || {
+                let Ok(devices) = discover(DEFAULT_DISCOVERY_TIMEOUT) else {
+                    return;
+                };
+                let state = discovery_handle.state::<Mutex<HeosClient>>();
+                let Ok(mut client) = state.lock() else {
+                    return;
+                };
+                if let Some(device) = devices.first() {
+                    if client.host().is_empty() {
+                        client.set_host(device.host.clone());
+                    }
+                }
+            });
+
             Ok(())
         })
         .run(tauri::generate_context!())
@@ -3,6 +3,7 @@
  */
 
 import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 
 // ============================================================================
 // Types
@@ -42,6 +43,16 @@ export interface MusicSource {
 
 export type PlayState = "play" | "pause" | "stop";
 
+export interface HeosDevice {
+	host: string;
+	friendly_name: string;
+	model_name?: string;
+	location: string;
+}
+
+/** Change event sent by the speaker, tagged by its HEOS command */
+export type HeosEvent = { command: string } & Record<string, unknown>;
+
 // ============================================================================
 // Connection
 // ============================================================================
@@ -60,6 +71,13 @@ export async function getHost(): Promise<string> {
 	return invoke("heos_get_host");
 }
 
+/**
+ * Search the LAN for HEOS devices
+ */
+export async function discover(): Promise<HeosDevice[]> {
+	return invoke("heos_discover");
+}
+
 /**
  * Connect to HEOS device
  */
@@ -100,6 +118,51 @@ export async function connectAndDiscover(playerName?: string): Promise<Player> {
 	return invoke("heos_connect_and_discover", { playerName });
 }
 
+// ============================================================================
+// Change Events
+// ============================================================================
+
+/**
+ * Start listening for HEOS change events on a dedicated connection
+ */
+export async function startEvents(): Promise<void> {
+	return invoke("heos_start_events");
+}
+
+/**
+ * Stop listening for HEOS change events
+ */
+export async function stopEvents(): Promise<void> {
+	return invoke("heos_stop_events");
+}
+
+/**
+ * Check if the event listener is registered for events right now
+ */
+export async function isListening(): Promise<boolean> {
+	return invoke("heos_is_listening");
+}
+
+/**
+ * Call `handler` for every HEOS change event
+ */
+export async function onEvent(handler: (event: HeosEvent) => void): Promise<UnlistenFn> {
+	return listen<HeosEvent>("heosEvent", (event) => handler(event.payload));
+}
+
+/**
+ * Call `handler` with `false` when the event connection is lost, and with `true` once the
+ * listener has reconnected and registered for events again
+ */
+export async function onEventsStatus(handler: (listening: boolean) => void): Promise<UnlistenFn> {
+	const started = await listen("heosEventsStarted", () => handler(true));
+	const stopped = await listen("heosEventsStopped", () => handler(false));
+	return () => {
+		started();
+		stopped();
+	};
+}
+
 // ============================================================================
 // Player Discovery
 // ============================================================================
@@ -303,12 +366,18 @@ export const heosService = {
 	// Connection
 	setHost,
 	getHost,
+	discover,
 	connect,
 	disconnect,
 	heartbeat,
 	isConnected,
 	connectAndDiscover,
 
+	// Change events
+	startEvents,
+	stopEvents,
+	onEvent,
+
 	// Players
 	getPlayers,
 	setPlayerId,